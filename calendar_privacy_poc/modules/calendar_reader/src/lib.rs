@@ -23,7 +23,16 @@ impl exports::local::calendar_privacy::calendar_api::Guest for Component {
     }
     
     fn get_events_sensitive() -> Vec<exports::local::calendar_privacy::calendar_api::CalendarEvent> {
-         vec![]
+        // The kind of detail that must never reach a search engine.
+        vec![
+            exports::local::calendar_privacy::calendar_api::CalendarEvent {
+                title: "Oncology follow-up".to_string(),
+                start: "2023-11-02T14:00:00Z".to_string(),
+                end: "2023-11-02T15:00:00Z".to_string(),
+                location: "St. Mary's Hospital".to_string(),
+                description: "Dr. Patel, +44 20 7946 0958, results to jane.doe@example.com".to_string(),
+            }
+        ]
     }
 }
 
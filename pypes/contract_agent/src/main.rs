@@ -1,6 +1,6 @@
 use anyhow::Result;
 use clap::Parser;
-use pypes_analyser::{verify, Blueprint};
+use pypes_analyser::{verify, Blueprint, Catalogue, Connection, Environment, Trust};
 use std::collections::HashMap;
 
 #[derive(Parser)]
@@ -25,7 +25,10 @@ fn main() -> Result<()> {
 
     // 2. Verify
     println!("🛡️  Running Safety Verification (Pypes Analyser)...");
-    match verify(&blueprint) {
+    // The contract names components that are not fetched yet; checks that need
+    // their contents wait for the run.
+    let env = Environment { pending: blueprint.components.keys().cloned().collect(), ..Default::default() };
+    match verify(&blueprint, &Catalogue::builtin(), &env) {
         Ok(_) => {
             println!("✅ Contract Verified SAFE.");
             println!("🚀 Executing Agent with these capabilities...");
@@ -46,7 +49,7 @@ fn main() -> Result<()> {
 fn generate_blueprint_from_prompt(prompt: &str) -> Blueprint {
     let lower = prompt.to_lowercase();
     let mut components = HashMap::new();
-    let mut wiring = HashMap::new();
+    let mut wiring: HashMap<String, Connection> = HashMap::new();
 
     // Default: Always need the Agent core
     components.insert("agent".to_string(), "modules/agent.wasm".to_string());
@@ -60,17 +63,17 @@ fn generate_blueprint_from_prompt(prompt: &str) -> Blueprint {
     if needs_calendar {
         components.insert("calendar".to_string(), "modules/calendar.wasm".to_string());
         // Link Agent -> Calendar
-        wiring.insert("agent.local:calendar/read".to_string(), "calendar.local:calendar/read".to_string());
+        wiring.insert("agent.local:calendar/read".to_string(), Connection::Simple("calendar.local:calendar/read".to_string()));
         // Link Calendar -> Host FS (Internal Data)
-        wiring.insert("calendar.wasi:filesystem/types".to_string(), "host.wasi:filesystem/types".to_string());
+        wiring.insert("calendar.wasi:filesystem/types".to_string(), Connection::Simple("host.wasi:filesystem/types".to_string()));
     }
 
     if needs_search {
         components.insert("search".to_string(), "modules/search.wasm".to_string());
         // Link Agent -> Search
-        wiring.insert("agent.local:search/query".to_string(), "search.local:search/query".to_string());
+        wiring.insert("agent.local:search/query".to_string(), Connection::Simple("search.local:search/query".to_string()));
         // Link Search -> Host HTTP (Exfiltration / Untrusted)
-        wiring.insert("search.wasi:http/outgoing-handler".to_string(), "host.wasi:http/outgoing-handler".to_string());
+        wiring.insert("search.wasi:http/outgoing-handler".to_string(), Connection::Simple("host.wasi:http/outgoing-handler".to_string()));
         
         // Note: In our tagging logic, 'search' export might imply untrusted input to the consumer.
     }
@@ -78,30 +81,40 @@ fn generate_blueprint_from_prompt(prompt: &str) -> Blueprint {
     if needs_delete {
         let is_proposal = lower.contains("propose") || lower.contains("safely");
         
-        // If 'calendar' is already added, we just add the wire. If not, add it.
-        if !components.contains_key("calendar") {
-            components.insert("calendar".to_string(), "modules/calendar.wasm".to_string());
-            wiring.insert("calendar.wasi:filesystem/types".to_string(), "host.wasi:filesystem/types".to_string());
-        }
-        
         if is_proposal {
-             // Safe Proposal Pattern: Wires to a proposal interface (mocked)
-             wiring.insert("agent.local:calendar/propose_delete".to_string(), "host.local:calendar/propose_delete".to_string());
+             // Safe Proposal Pattern: Wires to a component that only queues proposed
+             // deletions, and an operator approves each call on the wire
+             components.insert("proposals".to_string(), "modules/proposals.wasm".to_string());
+             wiring.insert(
+                 "agent.local:calendar/propose_delete".to_string(),
+                 Connection::through("proposals.local:calendar/propose_delete", &["approval"]),
+             );
         } else {
+             // If 'calendar' is already added, we just add the wire. If not, add it.
+             if !components.contains_key("calendar") {
+                 components.insert("calendar".to_string(), "modules/calendar.wasm".to_string());
+                 wiring.insert("calendar.wasi:filesystem/types".to_string(), Connection::Simple("host.wasi:filesystem/types".to_string()));
+             }
              // Dangerous Direct Delete
-             wiring.insert("agent.local:calendar/delete".to_string(), "calendar.local:calendar/delete".to_string());
+             wiring.insert("agent.local:calendar/delete".to_string(), Connection::Simple("calendar.local:calendar/delete".to_string()));
         }
     }
 
     if needs_email {
         components.insert("emailer".to_string(), "modules/emailer.wasm".to_string());
-        wiring.insert("agent.local:email/send".to_string(), "emailer.local:email/send".to_string());
-        wiring.insert("emailer.wasi:http/outgoing-handler".to_string(), "host.wasi:http/outgoing-handler".to_string());
+        wiring.insert("agent.local:email/send".to_string(), Connection::Simple("emailer.local:email/send".to_string()));
+        wiring.insert("emailer.wasi:http/outgoing-handler".to_string(), Connection::Simple("host.wasi:http/outgoing-handler".to_string()));
     }
 
     Blueprint {
         components,
         wiring,
         workflow: None,
+        // The agent is the component the user's request is handed to.
+        trust: Trust {
+            untrusted_inputs: vec!["agent".to_string()],
+            sanitisers: vec![],
+        },
+        guards: Default::default(),
     }
 }
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+use crate::workflow::val_to_json;
+
+/// A call waiting for an operator's decision.
+pub struct ApprovalRequest {
+    /// e.g. "calendar::local:calendar/propose_delete.propose (from agent)"
+    pub call: String,
+    /// Decoded arguments, named from the provider's WIT ("arg0", ... without it).
+    pub arguments: Vec<(String, Value)>,
+}
+
+/// Decides whether a call through an `approval` wire goes ahead. Called off the
+/// async runtime, so implementations may block.
+pub trait Approver: Send + Sync {
+    fn approve(&self, request: &ApprovalRequest) -> Result<bool>;
+}
+
+/// Asks on the terminal. Anything but "y" or "yes" denies.
+pub struct TerminalApprover;
+
+impl Approver for TerminalApprover {
+    fn approve(&self, request: &ApprovalRequest) -> Result<bool> {
+        println!("   🙋 Approval needed: {}", request.call);
+        for (name, value) in &request.arguments {
+            println!("        {} = {}", name, value);
+        }
+        print!("      Approve? [y/N] ");
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer).context("Failed to read approval from stdin")?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+/// Replays decisions from a file, one per call in order: `approve` or `deny` per
+/// line, `#` starts a comment. Calls beyond the end of the file are denied.
+pub struct ScriptedApprover {
+    decisions: Mutex<VecDeque<bool>>,
+}
+
+impl ScriptedApprover {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read approvals from {:?}", path))?;
+        let mut decisions = VecDeque::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            match line {
+                "" => {}
+                "approve" => decisions.push_back(true),
+                "deny" => decisions.push_back(false),
+                other => return Err(anyhow!("{:?} line {}: expected 'approve' or 'deny', got '{}'", path, i + 1, other)),
+            }
+        }
+        Ok(Self { decisions: Mutex::new(decisions) })
+    }
+}
+
+impl Approver for ScriptedApprover {
+    fn approve(&self, request: &ApprovalRequest) -> Result<bool> {
+        let decision = self.decisions.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+        let approved = decision.unwrap_or(false);
+        println!("   🙋 [approval] {} {}", if approved { "Approved" } else { "Denied" }, request.call);
+        Ok(approved)
+    }
+}
+
+/// Holds each call until the approver lets it through. Denied calls never reach
+/// the provider.
+pub struct ApprovalMiddleware {
+    approver: Arc<dyn Approver>,
+}
+
+impl ApprovalMiddleware {
+    pub fn new(approver: Arc<dyn Approver>) -> Self {
+        Self { approver }
+    }
+}
+
+impl Middleware for ApprovalMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let request = ApprovalRequest {
+            call: ctx.to_string(),
+            arguments: params.iter().enumerate().map(|(i, v)| {
+                let name = ctx.param_names.get(i).cloned().unwrap_or_else(|| format!("arg{}", i));
+                (name, val_to_json(v, &v.ty()))
+            }).collect(),
+        };
+        let approver = self.approver.clone();
+        Box::pin(async move {
+            let (approved, request) = tokio::task::spawn_blocking(move || {
+                (approver.approve(&request), request)
+            }).await?;
+            if approved? {
+                next(params).await
+            } else {
+                Err(anyhow!("Approval: {} was denied; call not forwarded", request.call))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn ctx() -> CallContext {
+        CallContext {
+            target_component: "calendar".to_string(),
+            target_interface: "local:calendar/propose-delete".to_string(),
+            function_name: "propose".to_string(),
+            caller_component: Some("agent".to_string()),
+            param_names: vec!["event".to_string()],
+        }
+    }
+
+    fn scripted(test: &str, script: &str) -> ScriptedApprover {
+        let path = std::env::temp_dir().join(format!("pypes-approvals-{}-{}.txt", std::process::id(), test));
+        std::fs::write(&path, script).unwrap();
+        let approver = ScriptedApprover::from_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        approver
+    }
+
+    /// The provider: counts the calls that reach it.
+    fn provider(calls: &Arc<AtomicUsize>) -> Next {
+        let calls = calls.clone();
+        Box::new(move |params| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(params) })
+        })
+    }
+
+    #[tokio::test]
+    async fn denied_calls_never_reach_the_provider() {
+        let approval = ApprovalMiddleware::new(Arc::new(scripted("deny", "approve\ndeny # not this one\n")));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let params = || vec![Val::String("standup".into())];
+
+        approval.handle(&ctx(), params(), provider(&calls)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let e = approval.handle(&ctx(), params(), provider(&calls)).await.unwrap_err();
+        assert!(e.to_string().contains("local:calendar/propose-delete.propose (from agent) was denied"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Past the end of the script.
+        approval.handle(&ctx(), params(), provider(&calls)).await.unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn scripts_hold_only_decisions() {
+        let path = std::env::temp_dir().join(format!("pypes-approvals-{}-bad.txt", std::process::id()));
+        std::fs::write(&path, "approve\nmaybe\n").unwrap();
+        let e = ScriptedApprover::from_file(&path).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(e.to_string().contains("line 2: expected 'approve' or 'deny', got 'maybe'"));
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+use crate::workflow::val_to_json;
+
+/// `prev_hash` of the first record in a log.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One proxied call, as recorded in the audit log.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, from 0.
+    pub seq: u64,
+    /// When the call returned, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub caller: Option<String>,
+    pub target: String,
+    pub interface: String,
+    pub function: String,
+    /// Arguments by WIT parameter name ("arg0", ... without WIT).
+    pub args: serde_json::Map<String, Value>,
+    /// `null` when the call failed.
+    pub results: Option<Vec<Value>>,
+    pub duration_ms: u64,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    /// `hash` of the previous record, which ties every record to all before it.
+    pub prev_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Ok,
+    Error,
+}
+
+/// A line of the log: the entry plus the SHA-256 of the entry's own JSON.
+#[derive(Serialize, Deserialize)]
+struct AuditRecord {
+    #[serde(flatten)]
+    entry: AuditEntry,
+    hash: String,
+}
+
+fn hash_of(entry: &AuditEntry) -> String {
+    let body = serde_json::to_string(entry).expect("audit entries always serialize");
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+/// Checks every record's hash and its link to the one before, and that the log
+/// still holds the record hashed `anchor`, a head printed after an earlier run.
+/// The chain alone cannot tell records cut off the end from records never
+/// written; the anchor can. Returns how many records there are and the hash of
+/// the last one.
+pub fn verify_file(path: &Path, anchor: Option<&str>) -> Result<(u64, String)> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read audit log {:?}", path))?;
+    let mut prev_hash = GENESIS.to_string();
+    let mut count = 0;
+    let mut anchored = anchor == Some(GENESIS);
+    for (i, line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        let record: AuditRecord = serde_json::from_str(line)
+            .with_context(|| format!("line {}: not an audit record", line_no))?;
+        if record.entry.seq != count {
+            return Err(anyhow!("line {}: seq is {}, expected {}", line_no, record.entry.seq, count));
+        }
+        if record.entry.prev_hash != prev_hash {
+            return Err(anyhow!("line {}: prev_hash does not match the previous record", line_no));
+        }
+        if hash_of(&record.entry) != record.hash {
+            return Err(anyhow!("line {}: hash does not match the record's contents", line_no));
+        }
+        anchored |= anchor == Some(record.hash.as_str());
+        prev_hash = record.hash;
+        count += 1;
+    }
+    if let Some(anchor) = anchor.filter(|_| !anchored) {
+        return Err(anyhow!("no record hashes to {}; records up to it have been removed", anchor));
+    }
+    Ok((count, prev_hash))
+}
+
+/// An append-only JSONL file of hash-chained records. Shared by every wire with
+/// `audit` middleware, so one chain covers the whole run.
+pub struct AuditLog {
+    tail: Mutex<Tail>,
+}
+
+struct Tail {
+    file: File,
+    next_seq: u64,
+    prev_hash: String,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, continuing the chain already in it. Refuses a
+    /// log that no longer verifies rather than extending a broken chain.
+    pub fn open(path: &Path) -> Result<Self> {
+        let (next_seq, prev_hash) = if path.exists() {
+            verify_file(path, None).with_context(|| format!("Refusing to extend audit log {:?}", path))?
+        } else {
+            (0, GENESIS.to_string())
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .with_context(|| format!("Failed to open audit log {:?}", path))?;
+        Ok(Self { tail: Mutex::new(Tail { file, next_seq, prev_hash }) })
+    }
+
+    /// Hash of the last record, to check later copies of the log against.
+    pub fn head(&self) -> String {
+        self.tail.lock().unwrap_or_else(|e| e.into_inner()).prev_hash.clone()
+    }
+
+    /// Fills in `seq` and `prev_hash` and appends the entry.
+    fn append(&self, mut entry: AuditEntry) -> Result<()> {
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        entry.seq = tail.next_seq;
+        entry.prev_hash = tail.prev_hash.clone();
+        let hash = hash_of(&entry);
+        let mut line = serde_json::to_string(&AuditRecord { entry, hash: hash.clone() })?;
+        line.push('\n');
+        tail.file.write_all(line.as_bytes())?;
+        tail.file.flush()?;
+        tail.next_seq += 1;
+        tail.prev_hash = hash;
+        Ok(())
+    }
+}
+
+/// Records every call through the wire, failed or not, in the run's audit log.
+/// A call that cannot be recorded fails, so the log never silently misses one.
+pub struct AuditMiddleware {
+    log: Arc<AuditLog>,
+}
+
+impl AuditMiddleware {
+    pub fn new(log: Arc<AuditLog>) -> Self {
+        Self { log }
+    }
+}
+
+impl Middleware for AuditMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let args = params.iter().enumerate().map(|(i, v)| {
+            let name = ctx.param_names.get(i).cloned().unwrap_or_else(|| format!("arg{}", i));
+            (name, val_to_json(v, &v.ty()))
+        }).collect();
+        let mut entry = AuditEntry {
+            seq: 0,
+            timestamp_ms: 0,
+            caller: ctx.caller_component.clone(),
+            target: ctx.target_component.clone(),
+            interface: ctx.target_interface.clone(),
+            function: ctx.function_name.clone(),
+            args,
+            results: None,
+            duration_ms: 0,
+            outcome: Outcome::Ok,
+            error: None,
+            prev_hash: String::new(),
+        };
+        let call = ctx.to_string();
+        let log = self.log.clone();
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = next(params).await;
+            entry.duration_ms = start.elapsed().as_millis() as u64;
+            entry.timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+            match &result {
+                Ok(vals) => entry.results = Some(vals.iter().map(|v| val_to_json(v, &v.ty())).collect()),
+                Err(e) => {
+                    entry.outcome = Outcome::Error;
+                    entry.error = Some(format!("{:#}", e));
+                }
+            }
+            log.append(entry).with_context(|| format!("Audit: failed to record call to {}", call))?;
+            result
+        })
+    }
+}
+
+/// `pypes audit verify <LOG> [--anchor <HASH>]`
+pub fn verify_command(path: &Path, anchor: Option<&str>) -> Result<()> {
+    match verify_file(path, anchor) {
+        Ok((count, head)) => {
+            println!("✅ Audit log {:?} is intact: {} records, head {}", path, count, head);
+            Ok(())
+        }
+        Err(e) => {
+            println!("❌ Audit log {:?} does not verify: {:#}", path, e);
+            Err(anyhow!("Audit log verification failed"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh log path for one test.
+    fn log_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pypes-audit-{}-{}.jsonl", std::process::id(), test));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(function: &str) -> AuditEntry {
+        AuditEntry {
+            seq: 0,
+            timestamp_ms: 1,
+            caller: Some("agent".to_string()),
+            target: "calendar".to_string(),
+            interface: "local:calendar/read".to_string(),
+            function: function.to_string(),
+            args: serde_json::Map::new(),
+            results: Some(vec![Value::String("busy".to_string())]),
+            duration_ms: 0,
+            outcome: Outcome::Ok,
+            error: None,
+            prev_hash: String::new(),
+        }
+    }
+
+    /// Writes a log of `calls` records and returns the head after each one.
+    fn write_log(path: &Path, calls: &[&str]) -> Vec<String> {
+        let log = AuditLog::open(path).unwrap();
+        calls.iter().map(|call| {
+            log.append(entry(call)).unwrap();
+            log.head()
+        }).collect()
+    }
+
+    fn edit_lines(path: &Path, edit: impl FnOnce(&mut Vec<String>)) {
+        let mut lines: Vec<String> = fs::read_to_string(path).unwrap().lines().map(str::to_string).collect();
+        edit(&mut lines);
+        fs::write(path, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+    }
+
+    fn error(path: &Path, anchor: Option<&str>) -> String {
+        format!("{:#}", verify_file(path, anchor).unwrap_err())
+    }
+
+    #[test]
+    fn an_intact_chain_verifies() {
+        let path = log_path("intact");
+        let heads = write_log(&path, &["a", "b", "c"]);
+        assert_eq!(verify_file(&path, None).unwrap(), (3, heads[2].clone()));
+        assert_eq!(verify_file(&path, Some(&heads[1])).unwrap().0, 3);
+        assert_eq!(verify_file(&path, Some(GENESIS)).unwrap().0, 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reopening_continues_the_chain() {
+        let path = log_path("reopen");
+        write_log(&path, &["a"]);
+        let heads = write_log(&path, &["b"]);
+        assert_eq!(verify_file(&path, None).unwrap(), (2, heads[0].clone()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn edited_records_are_found() {
+        let path = log_path("edited");
+        write_log(&path, &["a", "b", "c"]);
+        edit_lines(&path, |lines| lines[1] = lines[1].replace("busy", "free"));
+        assert_eq!(error(&path, None), "line 2: hash does not match the record's contents");
+        assert!(AuditLog::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn removed_records_are_found() {
+        let path = log_path("removed");
+        write_log(&path, &["a", "b", "c"]);
+        edit_lines(&path, |lines| { lines.remove(1); });
+        assert_eq!(error(&path, None), "line 2: seq is 2, expected 1");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reordered_records_are_found() {
+        let path = log_path("reordered");
+        write_log(&path, &["a", "b"]);
+        edit_lines(&path, |lines| lines.swap(0, 1));
+        assert_eq!(error(&path, None), "line 1: seq is 1, expected 0");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncation_is_only_found_against_an_anchor() {
+        let path = log_path("truncated");
+        let heads = write_log(&path, &["a", "b", "c"]);
+        edit_lines(&path, |lines| lines.truncate(2));
+        // What is left is a valid chain on its own.
+        assert_eq!(verify_file(&path, None).unwrap(), (2, heads[1].clone()));
+        assert_eq!(
+            error(&path, Some(&heads[2])),
+            format!("no record hashes to {}; records up to it have been removed", heads[2])
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use pypes_analyser::BudgetConfig;
+use std::sync::Mutex;
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+use crate::workflow::val_to_json;
+
+/// Caps what a consumer can push through a wire: bytes per call, bytes and calls
+/// over the whole run. Arguments are measured as JSON, so a list of numbers costs
+/// what its digits cost, not its in-memory size.
+pub struct BudgetMiddleware {
+    config: BudgetConfig,
+    used: Mutex<Usage>,
+}
+
+#[derive(Default)]
+struct Usage {
+    calls: u64,
+    bytes: u64,
+}
+
+impl BudgetMiddleware {
+    pub fn new(config: BudgetConfig) -> Self {
+        Self { config, used: Mutex::new(Usage::default()) }
+    }
+
+    /// Charges one call of `bytes` to the wire, or explains which quota it would break.
+    /// Refused calls are not charged.
+    fn charge(&self, ctx: &CallContext, bytes: u64) -> Result<()> {
+        if let Some(limit) = self.config.max_call_bytes
+            && bytes > limit
+        {
+            return Err(anyhow!("Budget: call to {} carries {} bytes, over max_call_bytes = {}", ctx, bytes, limit));
+        }
+
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(limit) = self.config.max_calls
+            && used.calls + 1 > limit
+        {
+            return Err(anyhow!("Budget: call to {} would exceed max_calls = {} on this wire", ctx, limit));
+        }
+        if let Some(limit) = self.config.max_total_bytes
+            && used.bytes + bytes > limit
+        {
+            return Err(anyhow!(
+                "Budget: call to {} carries {} bytes with {} already sent, over max_total_bytes = {}",
+                ctx, bytes, used.bytes, limit
+            ));
+        }
+        used.calls += 1;
+        used.bytes += bytes;
+        Ok(())
+    }
+}
+
+impl Middleware for BudgetMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let bytes: usize = params.iter().map(|v| val_to_json(v, &v.ty()).to_string().len()).sum();
+        match self.charge(ctx, bytes as u64) {
+            Ok(()) => next(params),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> CallContext {
+        CallContext {
+            target_component: "search".to_string(),
+            target_interface: "local:search/query".to_string(),
+            function_name: "find".to_string(),
+            caller_component: Some("agent".to_string()),
+            param_names: Vec::new(),
+        }
+    }
+
+    fn budget(max_call_bytes: Option<u64>, max_total_bytes: Option<u64>, max_calls: Option<u64>) -> BudgetMiddleware {
+        BudgetMiddleware::new(BudgetConfig { max_call_bytes, max_total_bytes, max_calls })
+    }
+
+    #[test]
+    fn unset_limits_are_not_enforced() {
+        let budget = budget(None, None, None);
+        for _ in 0..100 {
+            budget.charge(&ctx(), u64::MAX / 1000).unwrap();
+        }
+    }
+
+    #[test]
+    fn one_call_may_not_exceed_max_call_bytes() {
+        let budget = budget(Some(10), None, None);
+        budget.charge(&ctx(), 10).unwrap();
+        let e = budget.charge(&ctx(), 11).unwrap_err();
+        assert!(e.to_string().contains("carries 11 bytes, over max_call_bytes = 10"));
+    }
+
+    #[test]
+    fn calls_add_up_to_max_total_bytes() {
+        let budget = budget(None, Some(100), None);
+        budget.charge(&ctx(), 60).unwrap();
+        budget.charge(&ctx(), 40).unwrap();
+        let e = budget.charge(&ctx(), 1).unwrap_err();
+        assert!(e.to_string().contains("carries 1 bytes with 100 already sent"));
+    }
+
+    #[test]
+    fn refused_calls_are_not_charged() {
+        let budget = budget(Some(50), Some(100), Some(3));
+        budget.charge(&ctx(), 50).unwrap();
+        assert!(budget.charge(&ctx(), 51).is_err());
+        assert!(budget.charge(&ctx(), 51).is_err());
+        budget.charge(&ctx(), 50).unwrap();
+        assert!(budget.charge(&ctx(), 1).is_err());
+        // The refusals above used none of the three calls.
+        budget.charge(&ctx(), 0).unwrap();
+        let e = budget.charge(&ctx(), 0).unwrap_err();
+        assert!(e.to_string().contains("would exceed max_calls = 3"));
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Context, Result};
+use pypes_analyser::{DlpAction, DlpConfig, DlpDetector};
+use regex::{Captures, Regex};
+use wasmtime::component::{List, OptionVal, Record, ResultVal, Tuple, Val, Variant};
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+
+/// Scans every string in a call's arguments, however deeply nested in records,
+/// lists, variants, options and results, for sensitive data. Depending on the
+/// wire's `dlp.action` a match rejects the call or is redacted before the
+/// provider sees it. Results are passed back unscanned: this guards what leaves
+/// the caller, not what the provider hands it.
+pub struct DlpMiddleware {
+    detectors: Vec<Detector>,
+    action: DlpAction,
+}
+
+struct Detector {
+    name: String,
+    regex: Regex,
+    // Checksum that weeds out look-alikes (Luhn for cards, mod-97 for IBANs).
+    valid: fn(&str) -> bool,
+}
+
+impl Detector {
+    fn builtin(detector: DlpDetector) -> Self {
+        let (name, pattern, valid): (&str, &str, fn(&str) -> bool) = match detector {
+            DlpDetector::CardNumber => ("card-number", r"\b(?:\d[ -]?){12,18}\d\b", luhn),
+            DlpDetector::Email => ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", any),
+            DlpDetector::PhoneNumber => (
+                "phone-number",
+                r"\+\d{1,3}(?:[ .-]?\d){6,14}\b|\(?\b\d{3}\)?[ .-]\d{3}[ .-]\d{4}\b",
+                any,
+            ),
+            DlpDetector::Iban => ("iban", r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]){11,30}\b", iban),
+        };
+        Self { name: name.to_string(), regex: Regex::new(pattern).expect("built-in DLP pattern is valid"), valid }
+    }
+
+    fn first_match<'t>(&self, text: &'t str) -> Option<&'t str> {
+        self.regex.find_iter(text).map(|m| m.as_str()).find(|m| (self.valid)(m))
+    }
+}
+
+impl DlpMiddleware {
+    pub fn new(config: &DlpConfig) -> Result<Self> {
+        let mut detectors: Vec<Detector> = config.detectors.iter().map(|&d| Detector::builtin(d)).collect();
+        for (name, pattern) in &config.patterns {
+            let regex = Regex::new(pattern).with_context(|| format!("Invalid DLP pattern '{}'", name))?;
+            detectors.push(Detector { name: name.clone(), regex, valid: any });
+        }
+        Ok(Self { detectors, action: config.action })
+    }
+
+    /// Applies the wire's action to one string argument.
+    fn scrub(&self, ctx: &CallContext, text: &str) -> Result<String> {
+        match self.action {
+            DlpAction::Reject => {
+                for detector in &self.detectors {
+                    if detector.first_match(text).is_some() {
+                        return Err(anyhow!("DLP: {} found in arguments to {}; call rejected", detector.name, ctx));
+                    }
+                }
+                Ok(text.to_string())
+            }
+            DlpAction::Redact => {
+                let mut text = text.to_string();
+                for detector in &self.detectors {
+                    let redacted = detector.regex.replace_all(&text, |caps: &Captures| {
+                        if (detector.valid)(&caps[0]) {
+                            println!("   🧹 [dlp] Redacted {} in arguments to {}", detector.name, ctx);
+                            format!("[REDACTED:{}]", detector.name)
+                        } else {
+                            caps[0].to_string()
+                        }
+                    });
+                    text = redacted.into_owned();
+                }
+                Ok(text)
+            }
+        }
+    }
+}
+
+impl Middleware for DlpMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let scrubbed: Result<Vec<Val>> = params.iter()
+            .map(|param| map_strings(param, &mut |text| self.scrub(ctx, text)))
+            .collect();
+        match scrubbed {
+            Ok(params) => next(params),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
+/// Rebuilds `val` with every string passed through `f`.
+pub fn map_strings(val: &Val, f: &mut dyn FnMut(&str) -> Result<String>) -> Result<Val> {
+    Ok(match val {
+        Val::String(s) => Val::String(f(s)?.into()),
+        Val::List(list) => {
+            let values = list.iter().map(|v| map_strings(v, f)).collect::<Result<Vec<_>>>()?;
+            Val::List(List::new(list.ty(), values.into())?)
+        }
+        Val::Record(record) => {
+            let fields = record.fields()
+                .map(|(name, v)| Ok((name, map_strings(v, f)?)))
+                .collect::<Result<Vec<_>>>()?;
+            Val::Record(Record::new(record.ty(), fields)?)
+        }
+        Val::Tuple(tuple) => {
+            let values = tuple.values().iter().map(|v| map_strings(v, f)).collect::<Result<Vec<_>>>()?;
+            Val::Tuple(Tuple::new(tuple.ty(), values.into())?)
+        }
+        Val::Variant(variant) => {
+            let payload = variant.payload().map(|v| map_strings(v, f)).transpose()?;
+            Val::Variant(Variant::new(variant.ty(), variant.discriminant(), payload)?)
+        }
+        Val::Option(option) => {
+            let value = option.value().map(|v| map_strings(v, f)).transpose()?;
+            Val::Option(OptionVal::new(option.ty(), value)?)
+        }
+        Val::Result(result) => {
+            let value = match result.value() {
+                Ok(v) => Ok(v.map(|v| map_strings(v, f)).transpose()?),
+                Err(v) => Err(v.map(|v| map_strings(v, f)).transpose()?),
+            };
+            Val::Result(ResultVal::new(result.ty(), value)?)
+        }
+        other => other.clone(),
+    })
+}
+
+fn any(_: &str) -> bool {
+    true
+}
+
+fn luhn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits.iter().rev().enumerate().map(|(i, &d)| {
+        if i % 2 == 1 {
+            let doubled = d * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            d
+        }
+    }).sum();
+    sum.is_multiple_of(10)
+}
+
+fn iban(candidate: &str) -> bool {
+    let compact: String = candidate.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(15..=34).contains(&compact.len()) {
+        return false;
+    }
+    // Move the country code and check digits to the end, read letters as 10..35,
+    // and the whole number must be 1 mod 97.
+    let rearranged = compact[4..].chars().chain(compact[..4].chars());
+    let mut remainder = 0u32;
+    for c in rearranged {
+        let Some(value) = c.to_digit(36) else { return false };
+        for digit in value.to_string().chars() {
+            remainder = (remainder * 10 + digit.to_digit(10).unwrap_or_default()) % 97;
+        }
+    }
+    remainder == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::{Component, Linker, Type};
+    use wasmtime::{Engine, Store};
+
+    /// Parameter types of `f(event: record { title: string, hour: u32 }, names: list<string>,
+    /// note: variant { text(string), none }, reply: result<string, string>, cc: option<string>)`.
+    fn param_types() -> Vec<Type> {
+        let engine = Engine::default();
+        let component = Component::new(&engine, r#"(component
+            (core module $m
+                (memory (export "memory") 1)
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+                (func (export "f") (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32)))
+            (core instance $i (instantiate $m))
+            (type $event' (record (field "title" string) (field "hour" u32)))
+            (export $event "event" (type $event'))
+            (type $note' (variant (case "text" string) (case "none")))
+            (export $note "note" (type $note'))
+            (func $f (param "event" $event) (param "names" (list string)) (param "note" $note)
+                (param "reply" (result string (error string))) (param "cc" (option string))
+                (canon lift (core func $i "f") (memory $i "memory") (realloc (func $i "realloc"))))
+            (export "f" (func $f)))"#).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine).instantiate(&mut store, &component).unwrap();
+        instance.get_func(&mut store, "f").unwrap().params(&store).to_vec()
+    }
+
+    fn shout(text: &str) -> Result<String> {
+        Ok(text.to_uppercase())
+    }
+
+    fn ctx() -> CallContext {
+        CallContext {
+            target_component: "search".to_string(),
+            target_interface: "local:search/query".to_string(),
+            function_name: "find".to_string(),
+            caller_component: Some("agent".to_string()),
+            param_names: vec!["text".to_string()],
+        }
+    }
+
+    fn dlp(detectors: &[DlpDetector], action: DlpAction) -> DlpMiddleware {
+        DlpMiddleware::new(&DlpConfig { detectors: detectors.to_vec(), patterns: Default::default(), action }).unwrap()
+    }
+
+    #[test]
+    fn map_strings_reaches_nested_strings() {
+        let types = param_types();
+        let [Type::Record(event), Type::List(names), Type::Variant(note), Type::Result(reply), Type::Option(cc)] =
+            types.as_slice()
+        else {
+            panic!("unexpected parameter types {:?}", types);
+        };
+        let string = |s: &str| Val::String(s.into());
+
+        let value = event.new_val([("title", string("standup")), ("hour", Val::U32(9))]).unwrap();
+        let expected = event.new_val([("title", string("STANDUP")), ("hour", Val::U32(9))]).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), expected);
+
+        let value = names.new_val(vec![string("ada"), string("bob")].into()).unwrap();
+        let expected = names.new_val(vec![string("ADA"), string("BOB")].into()).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), expected);
+
+        let value = note.new_val("text", Some(string("call me"))).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), note.new_val("text", Some(string("CALL ME"))).unwrap());
+        let value = note.new_val("none", None).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), value);
+
+        let value = reply.new_val(Ok(Some(string("done")))).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), reply.new_val(Ok(Some(string("DONE")))).unwrap());
+        let value = reply.new_val(Err(Some(string("busy")))).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), reply.new_val(Err(Some(string("BUSY")))).unwrap());
+
+        let value = cc.new_val(Some(string("ada@example.org"))).unwrap();
+        assert_eq!(map_strings(&value, &mut shout).unwrap(), cc.new_val(Some(string("ADA@EXAMPLE.ORG"))).unwrap());
+    }
+
+    #[test]
+    fn map_strings_stops_at_the_first_error() {
+        let types = param_types();
+        let Type::List(names) = &types[1] else { panic!("unexpected parameter types {:?}", types) };
+        let value = names.new_val(vec![Val::String("ada".into()), Val::String("bob".into())].into()).unwrap();
+        let mut seen = Vec::new();
+        let e = map_strings(&value, &mut |text| {
+            seen.push(text.to_string());
+            Err(anyhow!("no {}", text))
+        }).unwrap_err();
+        assert_eq!(e.to_string(), "no ada");
+        assert_eq!(seen, ["ada"]);
+    }
+
+    #[test]
+    fn luhn_accepts_only_valid_card_numbers() {
+        assert!(luhn("4111 1111 1111 1111"));
+        assert!(luhn("5500-0000-0000-0004"));
+        assert!(!luhn("4111 1111 1111 1112"));
+        // Valid checksum, but too short for a card.
+        assert!(!luhn("0000 0000 00"));
+    }
+
+    #[test]
+    fn iban_checks_mod_97() {
+        assert!(iban("GB82 WEST 1234 5698 7654 32"));
+        assert!(iban("DE89370400440532013000"));
+        assert!(!iban("GB82 WEST 1234 5698 7654 33"));
+        assert!(!iban("GB82 WEST 12"));
+        assert!(!iban("GB82-WEST-1234-5698-7654-32"));
+    }
+
+    #[test]
+    fn detectors_find_what_they_are_for() {
+        let card = Detector::builtin(DlpDetector::CardNumber);
+        assert_eq!(card.first_match("pay with 4111 1111 1111 1111 today"), Some("4111 1111 1111 1111"));
+        assert_eq!(card.first_match("order 4111 1111 1111 1112"), None);
+
+        let email = Detector::builtin(DlpDetector::Email);
+        assert_eq!(email.first_match("mail ada@example.org now"), Some("ada@example.org"));
+        assert_eq!(email.first_match("ada at example dot org"), None);
+
+        let phone = Detector::builtin(DlpDetector::PhoneNumber);
+        assert!(phone.first_match("call +44 20 7946 0958").is_some());
+        assert!(phone.first_match("call (555) 123-4567").is_some());
+        assert_eq!(phone.first_match("room 1234"), None);
+
+        let iban = Detector::builtin(DlpDetector::Iban);
+        assert_eq!(iban.first_match("to DE89370400440532013000."), Some("DE89370400440532013000"));
+    }
+
+    #[test]
+    fn reject_fails_on_any_detector() {
+        let dlp = dlp(&[DlpDetector::Email, DlpDetector::CardNumber], DlpAction::Reject);
+        assert_eq!(dlp.scrub(&ctx(), "lunch on friday").unwrap(), "lunch on friday");
+        let e = dlp.scrub(&ctx(), "ask ada@example.org").unwrap_err();
+        assert!(e.to_string().contains("email found in arguments to search::local:search/query.find"));
+    }
+
+    #[test]
+    fn redact_replaces_valid_matches_only() {
+        let dlp = dlp(&[DlpDetector::CardNumber], DlpAction::Redact);
+        assert_eq!(
+            dlp.scrub(&ctx(), "4111 1111 1111 1111 or 4111 1111 1111 1112").unwrap(),
+            "[REDACTED:card-number] or 4111 1111 1111 1112"
+        );
+    }
+
+    #[test]
+    fn custom_patterns_are_detectors() {
+        let config = DlpConfig {
+            detectors: Vec::new(),
+            patterns: [("ticket".to_string(), "TKT-[0-9]+".to_string())].into(),
+            action: DlpAction::Redact,
+        };
+        let dlp = DlpMiddleware::new(&config).unwrap();
+        assert_eq!(dlp.scrub(&ctx(), "see TKT-42").unwrap(), "see [REDACTED:ticket]");
+
+        let config = DlpConfig { patterns: [("bad".to_string(), "(".to_string())].into(), ..config };
+        assert!(DlpMiddleware::new(&config).is_err());
+    }
+}
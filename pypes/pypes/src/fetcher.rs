@@ -24,14 +24,11 @@ impl ComponentFetcher {
         Ok(cache)
     }
 
-    /// Fetch a component from a remote:// URI
+    /// Where `uri` is cached, as (cache directory, registry, skill, version).
     /// Format: remote://registry.example.com/skill-name@version
-    pub async fn fetch(&self, uri: &str) -> Result<PathBuf> {
-        if !uri.starts_with("remote://") {
-            return Err(anyhow!("Invalid remote URI: {}", uri));
-        }
-
-        let without_scheme = uri.strip_prefix("remote://").unwrap();
+    fn locate<'u>(&self, uri: &'u str) -> Result<(PathBuf, &'u str, &'u str, &'u str)> {
+        let without_scheme = uri.strip_prefix("remote://")
+            .ok_or_else(|| anyhow!("Invalid remote URI: {}", uri))?;
         let parts: Vec<&str> = without_scheme.split('/').collect();
         
         if parts.len() < 2 {
@@ -48,12 +45,25 @@ impl ComponentFetcher {
         }
         
         let (skill_name, version) = (skill_parts[0], skill_parts[1]);
-        
-        // Check cache first
         let cache_path = self.cache_dir
             .join(registry)
             .join(format!("{}@{}", skill_name, version));
+        Ok((cache_path, registry, skill_name, version))
+    }
+
+    /// The cached copy of a remote component, without going to the network.
+    pub fn cached(&self, uri: &str) -> Result<Option<PathBuf>> {
+        let (cache_path, ..) = self.locate(uri)?;
+        let component_path = cache_path.join("component.wasm");
+        Ok(component_path.exists().then_some(component_path))
+    }
+
+    /// Fetch a component from a remote:// URI
+    /// Format: remote://registry.example.com/skill-name@version
+    pub async fn fetch(&self, uri: &str) -> Result<PathBuf> {
+        let (cache_path, registry, skill_name, version) = self.locate(uri)?;
         
+        // Check cache first
         let component_path = cache_path.join("component.wasm");
         
         if component_path.exists() {
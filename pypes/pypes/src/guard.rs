@@ -0,0 +1,221 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use wasmtime::component::{Component, ComponentType, Linker, Lower, TypedFunc, Val};
+use wasmtime::{Engine, Store};
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+use crate::sandbox::HostGrants;
+use crate::workflow::val_to_json;
+use crate::HostState;
+
+/// The interface a guard component exports; see `wit/guard.wit`.
+const GUARD_INTERFACE: &str = "pypes:guard/guard";
+
+/// `call-info` in `wit/guard.wit`.
+#[derive(ComponentType, Lower)]
+#[component(record)]
+struct CallInfo {
+    caller: Option<String>,
+    target: String,
+    interface: String,
+    function: String,
+    /// JSON object of the arguments by WIT parameter name.
+    args: String,
+    /// JSON of the wire's `<guard> = { ... }` table.
+    settings: String,
+}
+
+type CheckFunc = TypedFunc<(CallInfo,), (Result<(), String>,)>;
+
+/// A guard component, instantiated once and shared by every wire that lists it.
+/// It runs in its own empty sandbox: no filesystem, network, environment, clock
+/// or randomness, so all it can act on is what it is shown.
+pub struct Guard {
+    name: String,
+    store: Mutex<Store<HostState>>,
+    check: CheckFunc,
+}
+
+impl Guard {
+    pub async fn load(engine: &Engine, name: &str, path: &Path) -> Result<Self> {
+        let component = Component::from_file(engine, path)?;
+        let grants = HostGrants::default();
+        let mut linker = Linker::new(engine);
+        grants.add_to_linker(&mut linker)?;
+
+        let mut store = Store::new(engine, HostState::new(name, grants.build_ctx()?, None));
+        store.epoch_deadline_async_yield_and_update(1);
+        let instance = linker.instantiate_async(&mut store, &component).await?;
+        let check = {
+            let mut exports = instance.exports(&mut store);
+            let mut guard = exports.instance(GUARD_INTERFACE)
+                .ok_or_else(|| anyhow!("component does not export '{}'", GUARD_INTERFACE))?;
+            guard.typed_func::<(CallInfo,), (Result<(), String>,)>("check")
+                .with_context(|| format!("'{}.check' does not match wit/guard.wit", GUARD_INTERFACE))?
+        };
+        Ok(Self { name: name.to_string(), store: Mutex::new(store), check })
+    }
+
+    async fn check(&self, info: CallInfo) -> Result<Result<(), String>> {
+        let mut store = self.store.lock().await;
+        let (verdict,) = self.check.call_async(&mut *store, (info,)).await?;
+        self.check.post_return_async(&mut *store).await?;
+        Ok(verdict)
+    }
+}
+
+/// Asks a guard component about each call before it reaches the provider. A
+/// refusal, or a guard that traps, fails the call.
+pub struct GuardMiddleware {
+    guard: Arc<Guard>,
+    settings: String,
+}
+
+impl GuardMiddleware {
+    pub fn new(guard: Arc<Guard>, settings: &toml::Table) -> Self {
+        let settings = serde_json::to_string(settings).unwrap_or_else(|_| "{}".to_string());
+        Self { guard, settings }
+    }
+}
+
+impl Middleware for GuardMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let args: serde_json::Map<String, serde_json::Value> = params.iter().enumerate().map(|(i, v)| {
+            let name = ctx.param_names.get(i).cloned().unwrap_or_else(|| format!("arg{}", i));
+            (name, val_to_json(v, &v.ty()))
+        }).collect();
+        let info = CallInfo {
+            caller: ctx.caller_component.clone(),
+            target: ctx.target_component.clone(),
+            interface: ctx.target_interface.clone(),
+            function: ctx.function_name.clone(),
+            args: serde_json::Value::Object(args).to_string(),
+            settings: self.settings.clone(),
+        };
+        let call = ctx.to_string();
+        let guard = self.guard.clone();
+
+        Box::pin(async move {
+            let verdict = guard.check(info).await
+                .with_context(|| format!("Guard '{}' failed while checking {}", guard.name, call))?;
+            match verdict {
+                Ok(()) => next(params).await,
+                Err(reason) => Err(anyhow!("Guard '{}' refused {}: {}", guard.name, call, reason)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wasmtime::Config;
+
+    /// Lets through calls without arguments and refuses the rest with "has
+    /// arguments".
+    const GUARD: &str = r#"
+        (component
+            (core module $m
+                (memory (export "memory") 1)
+                (global $heap (mut i32) (i32.const 1024))
+                (data (i32.const 16) "has arguments")
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                    global.get $heap
+                    global.get $heap
+                    local.get 3
+                    i32.add
+                    global.set $heap)
+                ;; caller (3), target, interface, function, args, settings (2 each)
+                (func (export "check") (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+                    local.get 10
+                    i32.const 2
+                    i32.eq
+                    if
+                        i32.const 0
+                        i32.const 0
+                        i32.store8
+                    else
+                        i32.const 0
+                        i32.const 1
+                        i32.store8
+                        i32.const 4
+                        i32.const 16
+                        i32.store
+                        i32.const 8
+                        i32.const 13
+                        i32.store
+                    end
+                    i32.const 0)
+            )
+            (core instance $i (instantiate $m))
+            (type $info (record
+                (field "caller" (option string))
+                (field "target" string)
+                (field "interface" string)
+                (field "function" string)
+                (field "args" string)
+                (field "settings" string)
+            ))
+            (func $check (param "call" $info) (result (result (error string)))
+                (canon lift (core func $i "check") (memory $i "memory") (realloc (func $i "realloc"))))
+            (component $shim
+                (type $info (record
+                    (field "caller" (option string))
+                    (field "target" string)
+                    (field "interface" string)
+                    (field "function" string)
+                    (field "args" string)
+                    (field "settings" string)
+                ))
+                (import "t-info" (type $t (eq $info)))
+                (import "f-check" (func $check (param "call" $t) (result (result (error string)))))
+                (export $e "call-info" (type $t))
+                (export "check" (func $check) (func (param "call" $e) (result (result (error string)))))
+            )
+            (instance $inst (instantiate $shim (with "t-info" (type $info)) (with "f-check" (func $check))))
+            (export "pypes:guard/guard" (instance $inst))
+        )
+    "#;
+
+    fn ctx() -> CallContext {
+        CallContext {
+            target_component: "calendar".to_string(),
+            target_interface: "local:calendar/read".to_string(),
+            function_name: "busy".to_string(),
+            caller_component: Some("agent".to_string()),
+            param_names: vec!["day".to_string()],
+        }
+    }
+
+    /// The provider: counts the calls that reach it.
+    fn provider(calls: &Arc<AtomicUsize>) -> Next {
+        let calls = calls.clone();
+        Box::new(move |params| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(params) })
+        })
+    }
+
+    #[tokio::test]
+    async fn a_refusal_stops_the_call() {
+        let mut config = Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config).unwrap();
+        let path = std::env::temp_dir().join(format!("pypes-guard-{}.wat", std::process::id()));
+        std::fs::write(&path, GUARD).unwrap();
+        let guard = Guard::load(&engine, "no_args", &path).await;
+        let _ = std::fs::remove_file(&path);
+        let middleware = GuardMiddleware::new(Arc::new(guard.unwrap()), &toml::Table::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        middleware.handle(&ctx(), Vec::new(), provider(&calls)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let e = middleware.handle(&ctx(), vec![Val::String("friday".into())], provider(&calls)).await.unwrap_err();
+        assert_eq!(e.to_string(), "Guard 'no_args' refused calendar::local:calendar/read.busy (from agent): has arguments");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
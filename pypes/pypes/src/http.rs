@@ -0,0 +1,209 @@
+use http_body_util::{BodyExt, Limited};
+use pypes_analyser::HttpGrant;
+use std::fmt;
+use wasmtime::component::{Resource, ResourceTable};
+use wasmtime_wasi::preview2;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::types::{default_send_request, HostFutureIncomingResponse, OutgoingRequest};
+use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
+
+use crate::HostState;
+
+/// Why an outbound request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpDenied {
+    NotGranted,
+    Host(String),
+    Scheme { scheme: String, host: String },
+    Method(String),
+    BodyTooLarge { limit: u64, declared: u64 },
+}
+
+impl fmt::Display for HttpDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpDenied::NotGranted => write!(f, "component has no outbound HTTP grant"),
+            HttpDenied::Host(host) => write!(f, "host '{}' is not in the allowlist", host),
+            HttpDenied::Scheme { scheme, host } => write!(f, "'{}' is allowed, but not over {}", host, scheme),
+            HttpDenied::Method(method) => write!(f, "method '{}' is not allowed", method),
+            HttpDenied::BodyTooLarge { limit, declared } => {
+                write!(f, "body of {} bytes exceeds the {} byte limit", declared, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HttpDenied {}
+
+impl HttpDenied {
+    /// The `wasi:http` error the guest sees.
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            HttpDenied::NotGranted | HttpDenied::Host(_) | HttpDenied::Scheme { .. } => ErrorCode::HttpRequestDenied,
+            HttpDenied::Method(_) => ErrorCode::HttpRequestMethodInvalid,
+            HttpDenied::BodyTooLarge { declared, .. } => ErrorCode::HttpRequestBodySize(Some(*declared)),
+        }
+    }
+}
+
+/// Checks a request against a wire's `http` grant. `scheme` is "http" or
+/// "https"; `authority` may carry a port. Host names are compared ignoring case.
+pub fn check(
+    grant: Option<&HttpGrant>,
+    scheme: &str,
+    authority: &str,
+    method: &str,
+    content_length: Option<u64>,
+) -> Result<(), HttpDenied> {
+    let grant = grant.ok_or(HttpDenied::NotGranted)?;
+
+    let authority = authority.to_ascii_lowercase();
+    let host = authority.rsplit_once(':').map_or(authority.as_str(), |(host, _port)| host);
+    // Schemes of the patterns matching the host; `None` for a pattern without one.
+    let schemes: Vec<Option<String>> = grant.allowed_hosts.iter().filter_map(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        let (pattern_scheme, pattern) = match pattern.split_once("://") {
+            Some((pattern_scheme, pattern)) => (Some(pattern_scheme.to_string()), pattern),
+            None => (None, pattern.as_str()),
+        };
+        let matches = match pattern.strip_prefix("*.") {
+            Some(domain) => host.ends_with(&format!(".{}", domain)),
+            None => pattern == host || pattern == authority,
+        };
+        matches.then_some(pattern_scheme)
+    }).collect();
+    if schemes.is_empty() {
+        return Err(HttpDenied::Host(host.to_string()));
+    }
+    if !schemes.iter().any(|s| s.as_deref().is_none_or(|s| s == scheme)) {
+        return Err(HttpDenied::Scheme { scheme: scheme.to_string(), host: host.to_string() });
+    }
+
+    if !grant.allowed_methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+        return Err(HttpDenied::Method(method.to_string()));
+    }
+
+    if let (Some(limit), Some(declared)) = (grant.max_body_bytes, content_length)
+        && declared > limit
+    {
+        return Err(HttpDenied::BodyTooLarge { limit, declared });
+    }
+
+    Ok(())
+}
+
+impl WasiHttpView for HostState {
+    fn ctx(&mut self) -> &mut WasiHttpCtx { &mut self.http_ctx }
+    fn table(&mut self) -> &mut ResourceTable { &mut self.table }
+
+    fn send_request(
+        &mut self,
+        mut request: OutgoingRequest,
+    ) -> wasmtime::Result<Resource<HostFutureIncomingResponse>> {
+        let method = request.request.method().to_string();
+        let content_length = request.request.headers()
+            .get("content-length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+
+        let scheme = if request.use_tls { "https" } else { "http" };
+        if let Err(denied) = check(self.http.as_ref(), scheme, &request.authority, &method, content_length) {
+            println!("   ⛔ [{}] HTTP {} {}://{} refused: {}", self.component, method, scheme, request.authority, denied);
+            let code = denied.error_code();
+            let handle = preview2::spawn(async move { Ok(Err(code)) });
+            return Ok(WasiHttpView::table(self).push(HostFutureIncomingResponse::new(handle))?);
+        }
+
+        // Streamed bodies carry no content-length, so cap them as they are sent.
+        if let Some(limit) = self.http.as_ref().and_then(|g| g.max_body_bytes) {
+            request.request = request.request.map(|body| {
+                Limited::new(body, limit as usize)
+                    .map_err(move |_| ErrorCode::HttpRequestBodySize(Some(limit)))
+                    .boxed()
+            });
+        }
+
+        default_send_request(self, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(hosts: &[&str], methods: &[&str], max_body_bytes: Option<u64>) -> HttpGrant {
+        HttpGrant {
+            allowed_hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            allowed_methods: methods.iter().map(|m| m.to_string()).collect(),
+            max_body_bytes,
+        }
+    }
+
+    #[test]
+    fn no_grant_allows_nothing() {
+        assert_eq!(check(None, "https", "example.com", "GET", None), Err(HttpDenied::NotGranted));
+    }
+
+    #[test]
+    fn hosts_match_exactly_or_by_subdomain() {
+        let grant = grant(&["api.example.com", "*.cdn.net"], &["GET"], None);
+        assert_eq!(check(Some(&grant), "https", "api.example.com", "GET", None), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "api.example.com:443", "GET", None), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "img.cdn.net", "GET", None), Ok(()));
+        // The wildcard is for subdomains only.
+        assert_eq!(check(Some(&grant), "https", "cdn.net", "GET", None), Err(HttpDenied::Host("cdn.net".to_string())));
+        assert_eq!(check(Some(&grant), "https", "evilcdn.net", "GET", None), Err(HttpDenied::Host("evilcdn.net".to_string())));
+        assert_eq!(
+            check(Some(&grant), "https", "example.com", "GET", None),
+            Err(HttpDenied::Host("example.com".to_string()))
+        );
+    }
+
+    #[test]
+    fn hosts_match_in_any_case() {
+        let grant = grant(&["API.Example.com", "*.CDN.net"], &["GET"], None);
+        assert_eq!(check(Some(&grant), "https", "api.example.COM", "GET", None), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "Img.cdn.NET:443", "GET", None), Ok(()));
+    }
+
+    #[test]
+    fn a_scheme_in_the_grant_must_match() {
+        let local = grant(&["HTTPS://localhost:8080"], &["GET"], None);
+        assert_eq!(check(Some(&local), "https", "localhost:8080", "GET", None), Ok(()));
+        assert!(check(Some(&local), "http", "localhost:8080", "GET", None).is_err());
+
+        let grant = grant(&["https://api.example.com", "*.cdn.net"], &["GET"], None);
+        assert_eq!(check(Some(&grant), "https", "api.example.com", "GET", None), Ok(()));
+        assert_eq!(
+            check(Some(&grant), "http", "api.example.com", "GET", None),
+            Err(HttpDenied::Scheme { scheme: "http".to_string(), host: "api.example.com".to_string() })
+        );
+        // No scheme allows either.
+        assert_eq!(check(Some(&grant), "http", "img.cdn.net", "GET", None), Ok(()));
+    }
+
+    #[test]
+    fn a_host_with_a_port_can_be_granted_alone() {
+        let grant = grant(&["localhost:8080"], &["GET"], None);
+        assert_eq!(check(Some(&grant), "https", "localhost:8080", "GET", None), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "localhost:9090", "GET", None), Err(HttpDenied::Host("localhost".to_string())));
+    }
+
+    #[test]
+    fn methods_match_in_any_case() {
+        let grant = grant(&["example.com"], &["get"], None);
+        assert_eq!(check(Some(&grant), "https", "example.com", "GET", None), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "example.com", "POST", None), Err(HttpDenied::Method("POST".to_string())));
+    }
+
+    #[test]
+    fn declared_bodies_fit_the_limit() {
+        let grant = grant(&["example.com"], &["POST"], Some(10));
+        assert_eq!(check(Some(&grant), "https", "example.com", "POST", Some(10)), Ok(()));
+        assert_eq!(check(Some(&grant), "https", "example.com", "POST", None), Ok(()));
+        assert_eq!(
+            check(Some(&grant), "https", "example.com", "POST", Some(11)),
+            Err(HttpDenied::BodyTooLarge { limit: 10, declared: 11 })
+        );
+    }
+}
@@ -0,0 +1,694 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use pypes_analyser::{is_root_export, Blueprint, Catalogue, Connection, Environment, Report, ViolationType, verify};
+use std::fs;
+use std::path::PathBuf;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Mutex;
+use wasmtime::{Config, Engine, Store, component::{Component, Instance, InstancePre, Linker, ResourceTable, ResourceType, Val}};
+use wasmtime_wasi::preview2::{WasiCtx, WasiView};
+use wasmtime_wasi_http::WasiHttpCtx;
+use pypes_analyser::HttpGrant;
+use pypes_analyser::component_types::{ComponentTypes, Item};
+use pypes_analyser::wit_loader::WitLoader;
+
+pub mod approval;
+pub mod audit;
+mod budget;
+mod dlp;
+mod fetcher;
+mod guard;
+mod http;
+mod plan;
+pub mod workflow;
+pub mod middleware;
+mod rate_limit;
+mod resources;
+mod sandbox;
+mod timeout;
+mod validate;
+
+use approval::{Approver, ScriptedApprover, TerminalApprover};
+use fetcher::ComponentFetcher;
+use middleware::Registry;
+use sandbox::HostGrants;
+use std::path::Path;
+
+#[derive(Parser)]
+#[clap(author, version, about, subcommand_negates_reqs = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(short, long, required = true)]
+    config: Option<PathBuf>,
+    #[clap(long)]
+    verify_only: bool,
+    /// What to run: `component[.interface].function`. A bare component runs its
+    /// `run` export.
+    #[clap(short, long)]
+    entrypoint: Option<String>,
+    /// The entrypoint's arguments, as a JSON array in parameter order.
+    #[clap(long)]
+    args: Option<String>,
+    #[clap(long)]
+    allow_unsafe: bool,
+    /// Capability catalogue (TOML). Defaults to the built-in catalogue.
+    #[clap(long)]
+    catalogue: Option<PathBuf>,
+    /// Write a machine-readable verification report to this file.
+    #[clap(long)]
+    report: Option<PathBuf>,
+    #[clap(long, value_enum, default_value_t = ReportFormat::Json)]
+    report_format: ReportFormat,
+    /// Decide `approval` calls from this file (`approve`/`deny` per line) instead
+    /// of asking on the terminal.
+    #[clap(long)]
+    approvals: Option<PathBuf>,
+    /// Where `audit` middleware records calls (appended to if it exists).
+    #[clap(long, default_value = "pypes-audit.jsonl")]
+    audit_log: PathBuf,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Inspect audit logs written by the `audit` middleware.
+    Audit {
+        #[clap(subcommand)]
+        command: AuditCommand,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum AuditCommand {
+    /// Check that no record has been altered, removed or reordered.
+    Verify {
+        log: PathBuf,
+        /// A head hash printed after an earlier run; records up to it must still
+        /// be there, which the chain alone cannot show for records cut off the end.
+        #[clap(long)]
+        anchor: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ReportFormat {
+    Json,
+    Sarif,
+}
+
+pub struct HostState {
+    component: String,
+    table: ResourceTable,
+    ctx: WasiCtx,
+    http_ctx: WasiHttpCtx,
+    // Outbound HTTP allowlist from the component's `host.wasi:http/*` wire.
+    http: Option<HttpGrant>,
+    // Set while a proxied call is inside the component. Still set once the call
+    // is over means it was abandoned part-way (a `timeout`), leaving the
+    // component's state unknown, so it takes no more calls.
+    in_call: bool,
+}
+
+impl HostState {
+    fn new(component: &str, ctx: WasiCtx, http: Option<HttpGrant>) -> Self {
+        Self {
+            component: component.to_string(),
+            table: ResourceTable::new(),
+            ctx,
+            http_ctx: WasiHttpCtx,
+            http,
+            in_call: false,
+        }
+    }
+}
+
+impl WasiView for HostState {
+    fn table(&mut self) -> &mut ResourceTable { &mut self.table }
+    fn ctx(&mut self) -> &mut WasiCtx { &mut self.ctx }
+}
+
+/// Each component lives in its own store so it only sees its own WASI sandbox.
+/// Proxied calls lock the provider's store for the duration of the call.
+pub type SharedStore = Arc<Mutex<Store<HostState>>>;
+
+/// How often running guests yield to the host, which is what lets the `timeout`
+/// middleware interrupt a call that never returns on its own.
+const EPOCH_TICK: std::time::Duration = std::time::Duration::from_millis(10);
+
+pub struct RunningComponent {
+    pub store: SharedStore,
+    pub instance: Instance,
+}
+
+/// Runs the `pypes` command line with the middleware in `registry` available to
+/// blueprints. Crates embedding pypes register their own middleware and call this
+/// from their `main`:
+///
+/// ```ignore
+/// let mut registry = pypes::middleware::Registry::builtin();
+/// registry.register("acme_guard", |setup| Ok(Arc::new(AcmeGuard::new(setup.settings)?)))?;
+/// pypes::run(registry).await
+/// ```
+pub async fn run(mut registry: Registry) -> Result<()> {
+    let args = Args::parse();
+    if let Some(Command::Audit { command: AuditCommand::Verify { log, anchor } }) = &args.command {
+        return audit::verify_command(log, anchor.as_deref());
+    }
+    // Required by clap whenever there is no subcommand.
+    let config_path = args.config.clone().context("--config is required")?;
+
+    println!("Loading blueprint from {:?}...", config_path);
+    let content = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
+    
+    let blueprint: Blueprint = toml::from_str(&content)
+        .context("Failed to parse TOML configuration")?;
+    // A blueprint with a workflow runs its steps; there is no entrypoint to call.
+    if blueprint.workflow.is_some() && (args.entrypoint.is_some() || args.args.is_some()) {
+        return Err(anyhow!("--entrypoint and --args cannot be used with a blueprint that has a workflow"));
+    }
+
+    let catalogue = match &args.catalogue {
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read capability catalogue: {:?}", path))?;
+            Catalogue::parse(&content)
+                .map_err(|e| anyhow!("Failed to parse capability catalogue {:?}: {}", path, e))?
+        }
+        None => Catalogue::builtin(),
+    };
+    // Resolve components up front: their WIT sizes the channels the analyser
+    // checks against `max_bits` budgets. Remote components are only fetched for
+    // a run; `--verify-only` uses the cache and leaves uncached ones pending.
+    let base_dir = config_path.parent().unwrap_or(Path::new("."));
+    let fetcher = ComponentFetcher::new()?;
+    let mut component_paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut env = Environment {
+        middleware: registry.names().map(str::to_string).collect(),
+        ..Default::default()
+    };
+
+    for (name, rel_path) in &blueprint.components {
+        let path = if !rel_path.starts_with("remote://") {
+            // Local file
+            base_dir.join(rel_path)
+        } else if !args.verify_only {
+            // Fetch from remote registry
+            fetcher.fetch(rel_path).await?
+        } else if let Some(path) = fetcher.cached(rel_path)? {
+            path
+        } else {
+            println!("   ⏳ '{}' is not fetched; its budgets are checked when it is fetched to run.", name);
+            env.pending.insert(name.clone());
+            continue;
+        };
+
+        // A binary not built yet is pending like an unfetched one: a run cannot
+        // load it, so its wires are checked by the run that can.
+        if path.exists() {
+            let types = ComponentTypes::load(&path)
+                .with_context(|| format!("Failed to load component {}", name))?;
+            env.types.insert(name.clone(), types);
+        } else {
+            println!("   ⚠️  Component '{}' not found at {:?}; its wires are type-checked once it is built.", name, path);
+            env.pending.insert(name.clone());
+        }
+        match find_wit(&path) {
+            Some(wit_path) => {
+                let loader = WitLoader::load(&wit_path)
+                    .with_context(|| format!("Failed to load WIT for component {}", name))?;
+                env.wit.insert(name.clone(), loader);
+            }
+            None => println!("   ⚠️  No WIT file found for '{}'; channels from it cannot be sized for `max_bits`.", name),
+        }
+        component_paths.insert(name.clone(), path);
+    }
+
+    if catalogue.heuristic_fallback {
+        for interface in catalogue.unknown_interfaces(&blueprint, &env) {
+            eprintln!("⚠️  Interface '{}' is not in the capability catalogue; classifying it by name heuristics.", interface);
+        }
+    }
+
+    println!("🛡️  Running Pypes Static Analysis...");
+    for channel in pypes_analyser::channels(&blueprint, &env) {
+        let bits = channel.bits.map_or("unknown".to_string(), pypes_analyser::format_bits);
+        match channel.max_bits {
+            Some(max_bits) => println!("   📏 {}: {} (budget {} bits)", channel.description, bits, max_bits),
+            None => println!("   📏 {}: {}", channel.description, bits),
+        }
+    }
+    let verdict = verify(&blueprint, &catalogue, &env);
+
+    if let Some(path) = &args.report {
+        let violations = verdict.as_ref().err().map_or(&[][..], |v| v.as_slice());
+        let report = Report::new(config_path.to_str().unwrap_or_default(), violations);
+        let content = match args.report_format {
+            ReportFormat::Json => report.to_json(),
+            ReportFormat::Sarif => report.to_sarif(),
+        };
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write verification report: {:?}", path))?;
+    }
+
+    match verdict {
+        Ok(_) => {
+            println!("✅ VERIFICATION PASSED.");
+        },
+        Err(violations) => {
+            eprintln!("❌ SAFETY VIOLATION(S) DETECTED!");
+            // A misconfigured wire would not run as written, so there is nothing
+            // to allow.
+            let misconfigured = violations.iter().any(|v| v.violation == ViolationType::MisconfiguredWire);
+            for v in violations {
+                eprintln!("   ⚠️  [{:?}] in component '{}': {}", v.violation, v.component, v.details);
+                for source in &v.sources {
+                    let path: Vec<String> = source.wires.iter().map(|w| w.to_string()).collect();
+                    let path = if path.is_empty() { "declared untrusted input".to_string() } else { path.join(", ") };
+                    if source.steps.is_empty() {
+                        eprintln!("      {:?} via {}", source.capability, path);
+                    } else {
+                        let path = if source.wires.is_empty() { format!("step '{}' itself", source.steps[0]) } else { path };
+                        eprintln!("      {:?} along steps {}, from {}", source.capability, source.steps.join(" -> "), path);
+                    }
+                }
+                if !v.cut.is_empty() {
+                    let cut: Vec<String> = v.cut.iter().map(|w| w.to_string()).collect();
+                    eprintln!("      ✂️  Removing {} would clear this.", cut.join(" and "));
+                }
+            }
+            if misconfigured {
+                eprintln!("Execution blocked. Misconfigured wires cannot be overridden; fix the blueprint.");
+                std::process::exit(1);
+            } else if !args.allow_unsafe {
+                eprintln!("Execution blocked. Use --allow-unsafe to override.");
+                std::process::exit(1);
+            } else {
+                eprintln!("⚠️  Proceeding despite violations (--allow-unsafe active).");
+            }
+        }
+    }
+
+    if args.verify_only {
+        return Ok(());
+    }
+
+    println!("Authorized. Initializing Pypes Generic Runtime (Typed Mode)...");
+    
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    config.async_support(true);
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+    let approver: Arc<dyn Approver> = match &args.approvals {
+        Some(path) => Arc::new(ScriptedApprover::from_file(path)?),
+        None => Arc::new(TerminalApprover),
+    };
+    let audited = blueprint.wiring.values().any(|c| c.middleware().iter().any(|m| m == "audit"));
+    let audit_log = if audited {
+        println!("📜 Recording audited calls to {:?}", args.audit_log);
+        Some(Arc::new(audit::AuditLog::open(&args.audit_log)?))
+    } else {
+        None
+    };
+    let services = middleware::Services { approver, audit_log };
+    for (name, path) in &blueprint.guards {
+        let path = if path.starts_with("remote://") { fetcher.fetch(path).await? } else { base_dir.join(path) };
+        println!(" - Loading guard '{}' from {:?}", name, path);
+        let guard = Arc::new(guard::Guard::load(&engine, name, &path).await
+            .with_context(|| format!("Failed to load guard {}", name))?);
+        registry.register(name, move |setup| Ok(Arc::new(guard::GuardMiddleware::new(guard.clone(), setup.settings))))?;
+    }
+    let ticker = engine.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(EPOCH_TICK).await;
+            ticker.increment_epoch();
+        }
+    });
+    let mut components = HashMap::new();
+    let mut stores: HashMap<String, SharedStore> = HashMap::new();
+    // One linker per component, so each consumer's imports resolve to the
+    // providers its own wires name.
+    let mut linkers: HashMap<String, Linker<HostState>> = HashMap::new();
+    let mut imports: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (name, path) in &component_paths {
+        println!(" - Loading component '{}' from {:?}", name, path);
+        let bytes = fs::read(path).with_context(|| format!("Failed to read component {}", name))?;
+        imports.insert(name.clone(), env.types[name].linked_imports());
+        let component = Component::from_binary(&engine, &bytes)
+            .with_context(|| format!("Failed to load component {}", name))?;
+        components.insert(name.clone(), component);
+
+        let grants = HostGrants::for_component(&blueprint, name)?;
+        println!("   Host grants for '{}': {:?}", name, grants);
+        let ctx = grants.build_ctx()
+            .with_context(|| format!("Failed to build sandbox for component {}", name))?;
+        let mut store = Store::new(&engine, HostState::new(name, ctx, grants.http.clone()));
+        store.epoch_deadline_async_yield_and_update(1);
+        stores.insert(name.clone(), Arc::new(Mutex::new(store)));
+
+        let mut linker = Linker::new(&engine);
+        grants.add_to_linker(&mut linker)?;
+        wasmtime_wasi_http::proxy::add_only_http_to_linker(&mut linker)?;
+        linkers.insert(name.clone(), linker);
+    }
+
+    let order = plan::instantiation_order(&blueprint, &imports)?;
+    println!("   Instantiation order: {}", order.join(", "));
+    let mut instances = HashMap::new();
+
+    // ProviderName -> List of (ExportName, LinkerName, Consumer, ConnectionConfig)
+    let mut wiring_map: HashMap<String, Vec<(String, String, String, Connection)>> = HashMap::new();
+    for (consumer_key, connection) in &blueprint.wiring {
+        let provider_key = connection.provider();
+        let p_parts: Vec<&str> = provider_key.splitn(2, '.').collect();
+        if p_parts.len() < 2 { continue; }
+        if p_parts[0] == "host" {
+            // Host interfaces are linked straight from WASI; nothing could run a chain.
+            if !connection.middleware().is_empty() {
+                return Err(anyhow!(
+                    "Wire '{}' -> '{}' has middleware, which only runs on wires between components",
+                    consumer_key, provider_key
+                ));
+            }
+            continue;
+        }
+        let provider = p_parts[0].to_string();
+        let export = p_parts[1].to_string();
+
+        let c_parts: Vec<&str> = consumer_key.splitn(2, '.').collect();
+        let import = if c_parts.len() == 2 { c_parts[1].to_string() } else { consumer_key.clone() };
+        let consumer = c_parts[0].to_string();
+
+        wiring_map.entry(provider).or_default().push((export, import, consumer, connection.clone()));
+    }
+
+    // Provider resources held by consumers, across every wire.
+    let bridge = Arc::new(resources::ResourceBridge::default());
+    // Each component's instantiation, once made; provider resources are turned into
+    // handles a consumer can hold against it.
+    let pres: HashMap<String, Arc<OnceLock<InstancePre<HostState>>>> =
+        components.keys().map(|name| (name.clone(), Arc::default())).collect();
+
+    // Providers come first, so each component's imports are linked by the time
+    // it is instantiated.
+    for name in &order {
+        let comp = components.get(name).unwrap();
+        let provider_store = stores.get(name).unwrap().clone();
+        let mut store = provider_store.lock().await;
+
+        let pre = linkers.get(name).unwrap().instantiate_pre(comp)
+            .with_context(|| format!("Failed to instantiate '{}'", name))?;
+        let instance = pre.instantiate_async(&mut *store).await
+            .with_context(|| format!("Failed to instantiate '{}'", name))?;
+        let _ = pres[name].set(pre);
+        println!("   ✅ Instantiated '{}'", name);
+        instances.insert(name.clone(), RunningComponent { store: provider_store.clone(), instance });
+
+        if let Some(wires) = wiring_map.get(name) {
+            for (export_name, linker_name, consumer, connection_config) in wires {
+                println!("      -> Wiring export '{}' to '{}' as '{}'", export_name, consumer, linker_name);
+                let (Some(consumer_comp), Some(consumer_linker)) = (components.get(consumer), linkers.get_mut(consumer)) else {
+                    println!("         ⚠️  Consumer '{}' is not a component in this blueprint.", consumer);
+                    continue;
+                };
+
+                // An interface wire proxies the functions the consumer imports from
+                // the interface; a root wire (`"consumer.fname" = "provider.fname"`,
+                // no `/` in the export) proxies one bare function. Verification has
+                // checked both against the provider's exports.
+                let wanted = env.types.get(consumer).and_then(|types| types.import(linker_name))
+                    .ok_or_else(|| anyhow!("'{}' does not import '{}'", consumer, linker_name))?;
+                let is_root = is_root_export(export_name);
+                // (Name the provider exports it as, name the consumer imports it as)
+                let (funcs, resource_names): (Vec<(String, String)>, &[String]) = match (wanted, is_root) {
+                    (Item::Func(_), true) => (vec![(export_name.clone(), linker_name.clone())], &[]),
+                    (Item::Instance { functions, resources }, false) => {
+                        (functions.keys().map(|f| (f.clone(), f.clone())).collect(), resources.as_slice())
+                    }
+                    (_, true) => return Err(anyhow!(
+                        "'{}' is a root function of '{}', but '{}' imports '{}' as an interface",
+                        export_name, name, consumer, linker_name
+                    )),
+                    (_, false) => return Err(anyhow!(
+                        "'{}' is an interface of '{}', but '{}' imports '{}' as a function",
+                        export_name, name, consumer, linker_name
+                    )),
+                };
+                let offered = env.types.get(name).and_then(|types| types.export(export_name));
+                // Linked only into this consumer's linker.
+                let mut target_linker = if is_root { consumer_linker.root() } else { consumer_linker.instance(linker_name)? };
+
+                // The interface's resources, as the consumer holds them (see resources.rs).
+                let mut wire_resources = Vec::new();
+                for resource in resource_names {
+                    let ty = instance.exports(&mut *store).instance(export_name).and_then(|mut i| i.resource(resource))
+                        .ok_or_else(|| anyhow!("'{}' does not export resource {}.{}, which '{}' imports", name, export_name, resource, consumer))?;
+                    let bridge = bridge.clone();
+                    let import = target_linker.resource(
+                        resource,
+                        ResourceType::host::<resources::ProxiedResource>(),
+                        move |_, rep| {
+                            bridge.release(rep);
+                            Ok(())
+                        },
+                    )?;
+                    wire_resources.push(resources::WireResource { name: resource.clone(), ty, import });
+                }
+                let wire_resources: Arc<[resources::WireResource]> = wire_resources.into();
+                let diode = connection_config.is_diode();
+                if diode && !wire_resources.is_empty() {
+                    println!("         🔒 Diode: calls passing resource handles will be refused.");
+                }
+                let consumer_pre = pres[consumer].clone();
+
+                // Middleware Integration
+                // One chain per wire, so stateful middleware (budgets, rate limits)
+                // sees calls to every function on it.
+                let mut chain: Vec<Arc<dyn middleware::Middleware>> = Vec::new();
+                for mw_name in connection_config.middleware() {
+                    chain.push(registry.build(mw_name, connection_config, &services)
+                        .with_context(|| format!("Failed to configure middleware '{}' for '{}'", mw_name, consumer))?);
+                }
+                if let Some(key) = connection_config.setting_names().into_iter()
+                    .find(|key| !connection_config.middleware().iter().any(|m| m == key))
+                {
+                    return Err(anyhow!(
+                        "Wire into '{}' sets `{}`, which names no middleware on it",
+                        consumer, key
+                    ));
+                }
+                let chain = middleware::Chain::new(chain);
+
+                for (func_name, import_name) in funcs {
+                    // Functions outside the wire's `functions` grant are not linked at
+                    // all: a consumer importing one cannot be instantiated.
+                    if !connection_config.is_function_granted(&func_name) {
+                        println!("         🚫 '{}' not granted on this wire; not linked.", func_name);
+                        continue;
+                    }
+                    // Get the runtime export from provider instance
+                    let provider_func = if is_root {
+                        instance.get_func(&mut *store, func_name.as_str())
+                    } else {
+                        instance.exports(&mut *store).instance(export_name).and_then(|mut i| i.func(&func_name))
+                    };
+                    let qualified = if is_root { func_name.clone() } else { format!("{}.{}", export_name, func_name) };
+                    let provider_func = provider_func
+                        .ok_or_else(|| anyhow!("'{}' does not export {}, which '{}' imports", name, qualified, consumer))?;
+                    let param_names = match offered {
+                        Some(Item::Func(func)) => Some(func),
+                        Some(Item::Instance { functions, .. }) => functions.get(&func_name),
+                        None => None,
+                    };
+                    let call_ctx = Arc::new(middleware::CallContext {
+                        target_component: name.clone(),
+                        target_interface: if is_root { String::new() } else { export_name.clone() },
+                        function_name: func_name.clone(),
+                        caller_component: Some(consumer.clone()),
+                        param_names: param_names
+                            .map(|f| f.params.iter().map(|(param, _)| param.clone()).collect())
+                            .unwrap_or_default(),
+                    });
+
+                    // The provider's export decides the shape of what comes back;
+                    // verification has already checked it against the import.
+                    let result_count = provider_func.results(&*store).len();
+                    if result_count != 1
+                        && connection_config.validate().and_then(|rules| rules.get(&func_name)).is_some_and(|r| r.result.is_some())
+                    {
+                        return Err(anyhow!(
+                            "`validate.{}.result` on the wire into '{}' needs one result, but {}::{} has {}",
+                            func_name, consumer, name, qualified, result_count
+                        ));
+                    }
+                    // Consumer handles are swapped for the provider's as these types.
+                    let param_types: Arc<[wasmtime::component::Type]> = provider_func.params(&*store).into();
+                    let chain = chain.clone();
+                    let provider_store = provider_store.clone();
+                    let bridge = bridge.clone();
+                    let consumer_pre = consumer_pre.clone();
+                    let wire_resources = wire_resources.clone();
+
+                    // Define the proxy in the consumer's linker, under the consumer's
+                    // import of the function.
+                    let res = target_linker.func_new_async(
+                        consumer_comp,
+                        &import_name,
+                        move |mut ctx, args, results| {
+                            let chain = chain.clone();
+                            let call_ctx = call_ctx.clone();
+                            let provider_store = provider_store.clone();
+                            let bridge = bridge.clone();
+                            let consumer_pre = consumer_pre.clone();
+                            let param_types = param_types.clone();
+                            let wire_resources = wire_resources.clone();
+
+                            // The innermost layer: the actual call into the provider.
+                            let terminal: middleware::Next = {
+                                let bridge = bridge.clone();
+                                let call_ctx = call_ctx.clone();
+                                Box::new(move |params: Vec<Val>| {
+                                    let provider_store = provider_store.clone();
+                                    let bridge = bridge.clone();
+                                    let call_ctx = call_ctx.clone();
+                                    Box::pin(async move {
+                                        // Placeholders, overwritten by the call.
+                                        let mut outputs = vec![Val::Bool(false); result_count];
+                                        let mut provider = provider_store.lock().await;
+                                        if provider.data().in_call {
+                                            return Err(anyhow!(
+                                                "'{}' is poisoned: an earlier call into it was abandoned part-way (timed out)",
+                                                call_ctx.target_component
+                                            ));
+                                        }
+                                        provider.data_mut().in_call = true;
+                                        let outcome = async {
+                                            bridge.drop_released(&call_ctx.target_component, &mut provider).await?;
+                                            provider_func.call_async(&mut *provider, &params, &mut outputs).await?;
+                                            provider_func.post_return_async(&mut *provider).await
+                                        }.await;
+                                        // Only reached if this future was not dropped mid-call.
+                                        provider.data_mut().in_call = false;
+                                        outcome?;
+                                        Ok(outputs)
+                                    })
+                                })
+                            };
+
+                            Box::new(async move {
+                                let wire = resources::WireResources {
+                                    bridge: &bridge,
+                                    provider: &call_ctx.target_component,
+                                    consumer: consumer_pre.get(),
+                                    resources: &wire_resources,
+                                    diode,
+                                };
+                                let params = args.iter().zip(param_types.iter())
+                                    .map(|(arg, ty)| wire.to_provider(&mut ctx, arg, ty))
+                                    .collect::<Result<Vec<_>>>()
+                                    .with_context(|| format!("Cannot pass the arguments of '{}'", call_ctx))?;
+                                resources::WireResources::end_borrows(&mut ctx, args).await?;
+
+                                let outputs = chain.call(call_ctx.clone(), params, terminal).await?;
+                                if outputs.len() != results.len() {
+                                    outputs.iter().for_each(|output| wire.discard(output));
+                                    return Err(anyhow!(
+                                        "Middleware returned {} results for '{}', which has {}",
+                                        outputs.len(), call_ctx, results.len()
+                                    ));
+                                }
+                                let mut outputs = outputs.into_iter();
+                                for (slot, value) in results.iter_mut().zip(&mut outputs) {
+                                    match wire.to_consumer(&mut ctx, value) {
+                                        Ok(value) => *slot = value,
+                                        Err(e) => {
+                                            outputs.for_each(|output| wire.discard(&output));
+                                            return Err(e.context(format!("Cannot return the results of '{}'", call_ctx)));
+                                        }
+                                    }
+                                }
+                                Ok(())
+                            })
+                        }
+                    );
+
+                    res.with_context(|| format!(
+                        "Failed to link {}::{} into '{}' as '{}'",
+                        name, qualified, consumer, linker_name
+                    ))?;
+                }
+            }
+        }
+    }
+
+    if let Some(workflow) = &blueprint.workflow {
+        workflow::execute(&instances, workflow).await?;
+        print_audit_head(&services);
+        return Ok(());
+    }
+
+    // "component[.interface].function"; a bare component runs its `run` export.
+    let entrypoint = args.entrypoint.unwrap_or("orchestrator".to_string());
+    let (component, function) = entrypoint.split_once('.').unwrap_or((&entrypoint, "run"));
+    let running = instances.get(component)
+        .ok_or_else(|| anyhow!("Entrypoint component '{}' is not in this blueprint", component))?;
+    println!("🚀 Running entrypoint '{}'...", entrypoint);
+    let mut store = running.store.lock().await;
+    let func = workflow::export_func(&running.instance, &mut store, function)
+        .ok_or_else(|| anyhow!("Entrypoint component '{}' has no '{}' function", component, function))?;
+
+    let call_args: Vec<serde_json::Value> = serde_json::from_str(args.args.as_deref().unwrap_or("[]"))
+        .context("--args must be a JSON array of the function's arguments")?;
+    let param_types = func.params(&*store);
+    if call_args.len() != param_types.len() {
+        return Err(anyhow!(
+            "'{}' takes {} argument(s), but --args has {}",
+            entrypoint, param_types.len(), call_args.len()
+        ));
+    }
+    let params = call_args.iter().zip(param_types.iter()).enumerate()
+        .map(|(i, (json, ty))| workflow::json_to_val(json, ty)
+            .with_context(|| format!("Argument {} of '{}' is not a {:?}", i + 1, entrypoint, ty)))
+        .collect::<Result<Vec<Val>>>()?;
+
+    let result_types = func.results(&*store);
+    let mut results = vec![Val::Bool(false); result_types.len()]; // Placeholder values
+    func.call_async(&mut *store, &params, &mut results).await
+        .with_context(|| format!("Entrypoint '{}' failed", entrypoint))?;
+    func.post_return_async(&mut *store).await?;
+
+    let mut outputs: Vec<serde_json::Value> = results.iter().zip(result_types.iter())
+        .map(|(val, ty)| workflow::val_to_json(val, ty))
+        .collect();
+    match outputs.len() {
+        0 => println!("✅ Result: (void)"),
+        1 => println!("✅ Result: {}", outputs.remove(0)),
+        _ => println!("✅ Result: {}", serde_json::Value::Array(outputs)),
+    }
+    print_audit_head(&services);
+
+    Ok(())
+}
+
+/// Prints where the audit chain ends, for `pypes audit verify --anchor` to check
+/// later copies of the log against.
+fn print_audit_head(services: &middleware::Services) {
+    if let Some(log) = &services.audit_log {
+        println!("📜 Audit log head: {} (verify later copies with --anchor)", log.head());
+    }
+}
+
+/// The WIT describing a component: `<name>.wit` next to it, or `interface.wit` in
+/// its directory (the layout of the remote component cache).
+fn find_wit(component_path: &Path) -> Option<PathBuf> {
+    let wit_path = component_path.with_extension("wit");
+    if wit_path.exists() {
+        return Some(wit_path);
+    }
+    let interface_wit = component_path.parent().unwrap_or(Path::new(".")).join("interface.wit");
+    interface_wit.exists().then_some(interface_wit)
+}
@@ -1,26 +1,85 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use pypes_analyser::Connection;
 use wasmtime::component::Val;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
+use crate::approval::{ApprovalMiddleware, Approver};
+use crate::audit::{AuditLog, AuditMiddleware};
+use crate::budget::BudgetMiddleware;
+use crate::dlp::DlpMiddleware;
+use crate::rate_limit::RateLimitMiddleware;
+use crate::timeout::TimeoutMiddleware;
+use crate::validate::ValidateMiddleware;
+
 pub struct CallContext {
     pub target_component: String,
+    // Empty for a root function export.
     pub target_interface: String,
     pub function_name: String,
     pub caller_component: Option<String>,
+    // From the provider's WIT; empty when it has none.
+    pub param_names: Vec<String>,
 }
 
+impl fmt::Display for CallContext {
+    // "llm_provider::local:calendar-privacy/llm-api.predict-state (from agent)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.target_interface.is_empty() {
+            write!(f, "{}::{}", self.target_component, self.function_name)?;
+        } else {
+            write!(f, "{}::{}.{}", self.target_component, self.target_interface, self.function_name)?;
+        }
+        if let Some(caller) = &self.caller_component {
+            write!(f, " (from {})", caller)?;
+        }
+        Ok(())
+    }
+}
+
+pub type MiddlewareFuture = Pin<Box<dyn Future<Output = Result<Vec<Val>>> + Send>>;
+
 // Next middleware in the chain
-pub type Next = Box<dyn Fn(Vec<Val>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Val>>> + Send>> + Send + Sync>;
+pub type Next = Box<dyn Fn(Vec<Val>) -> MiddlewareFuture + Send + Sync>;
 
+/// One layer of the onion around a proxied call.
+///
+/// `handle` receives the call's arguments and decides what happens: pass them (or
+/// rewritten ones) to `next` and inspect or rewrite the results, return results
+/// without calling `next` at all, or return an error to reject the call. An error
+/// reaches the calling component as a trap.
 pub trait Middleware: Send + Sync {
-    fn handle(
-        &self,
-        ctx: &CallContext,
-        params: Vec<Val>,
-        next: Next,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Val>>> + Send>>;
-
-    fn as_any(&self) -> &dyn std::any::Any;
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture;
+}
+
+/// The middleware configured on a wire, outermost first.
+#[derive(Clone, Default)]
+pub struct Chain(Arc<Vec<Arc<dyn Middleware>>>);
+
+impl Chain {
+    pub fn new(middleware: Vec<Arc<dyn Middleware>>) -> Self {
+        Self(Arc::new(middleware))
+    }
+
+    /// Runs `params` through every layer and finally `terminal`, the real call.
+    pub fn call(&self, ctx: Arc<CallContext>, params: Vec<Val>, terminal: Next) -> MiddlewareFuture {
+        let next = self.next_at(0, ctx, Arc::new(terminal));
+        next(params)
+    }
+
+    fn next_at(&self, index: usize, ctx: Arc<CallContext>, terminal: Arc<Next>) -> Next {
+        let chain = self.clone();
+        Box::new(move |params| match chain.0.get(index) {
+            Some(middleware) => {
+                let next = chain.next_at(index + 1, ctx.clone(), terminal.clone());
+                middleware.handle(&ctx, params, next)
+            }
+            None => terminal(params),
+        })
+    }
 }
 
 // Implementations
@@ -28,57 +87,251 @@ pub trait Middleware: Send + Sync {
 pub struct LoggingMiddleware;
 
 impl Middleware for LoggingMiddleware {
-    fn handle(
-        &self,
-        ctx: &CallContext,
-        params: Vec<Val>,
-        next: Next,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Val>>> + Send>> {
-        let func_name = ctx.function_name.clone();
-        let target = ctx.target_component.clone();
-        
-        // Clone params for logging (Val is Clone-ish, actually Val is cheap clone? No, Val can contain resources.)
-        // formatting Val is hard if it consumes resources. 
-        // We can just print "Args count" or try debug print if supported.
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let call = ctx.to_string();
+
         // Val `Debug` is available.
         let params_debug = format!("{:?}", params);
 
         Box::pin(async move {
-            println!("[Middleware] Call -> {}::{} Inputs: {}", target, func_name, params_debug);
+            println!("[Middleware] Call -> {} Inputs: {}", call, params_debug);
+            let start = std::time::Instant::now();
             let result = next(params).await;
             match &result {
-                Ok(vals) => println!("[Middleware] Return <- {}::{} Outputs: {:?}", target, func_name, vals),
-                Err(e) => println!("[Middleware] Error <- {}::{} Error: {:?}", target, func_name, e),
+                Ok(vals) => println!("[Middleware] Return <- {} ({}ms) Outputs: {:?}", call, start.elapsed().as_millis(), vals),
+                Err(e) => println!("[Middleware] Error <- {} Error: {:?}", call, e),
             }
             result
         })
     }
-    
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
-    }
 }
 
 pub struct NoOpMiddleware;
+
 impl Middleware for NoOpMiddleware {
-    fn handle(
-        &self,
-        _ctx: &CallContext,
-        params: Vec<Val>,
-        next: Next,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Val>>> + Send>> {
+    fn handle(&self, _ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
         next(params)
     }
+}
+
+/// What middleware shares across wires. Set up from the command line, never the
+/// blueprint, so a blueprint cannot approve its own proposals or pick where its
+/// calls are recorded.
+pub struct Services {
+    pub approver: Arc<dyn Approver>,
+    /// Open only when some wire has `audit` middleware.
+    pub audit_log: Option<Arc<AuditLog>>,
+}
+
+/// What a factory gets to build one wire's instance of its middleware.
+pub struct Setup<'a> {
+    /// The wire's `<name> = { ... }` table; empty if the wire has none.
+    pub settings: &'a toml::Table,
+    pub connection: &'a Connection,
+    pub services: &'a Services,
+}
+
+pub type Factory = Box<dyn Fn(&Setup) -> Result<Arc<dyn Middleware>> + Send + Sync>;
+
+/// The middleware a blueprint can name on its wires.
+pub struct Registry {
+    factories: HashMap<String, Factory>,
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self { factories: HashMap::new() }
+    }
+
+    /// Every middleware that ships with pypes.
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.insert("approval", |s| Ok(Arc::new(ApprovalMiddleware::new(s.services.approver.clone()))));
+        registry.insert("audit", |s| {
+            let log = s.services.audit_log.clone().ok_or_else(|| anyhow!("audit log is not open"))?;
+            Ok(Arc::new(AuditMiddleware::new(log)))
+        });
+        registry.insert("logging", |_| Ok(Arc::new(LoggingMiddleware)));
+        registry.insert("noop", |_| Ok(Arc::new(NoOpMiddleware)));
+        registry.insert("dlp", |s| Ok(Arc::new(DlpMiddleware::new(&s.connection.dlp().cloned().unwrap_or_default())?)));
+        registry.insert("budget", |s| Ok(Arc::new(BudgetMiddleware::new(s.connection.budget().copied().unwrap_or_default()))));
+        registry.insert("validate", |s| Ok(Arc::new(ValidateMiddleware::new(s.connection.validate().cloned().unwrap_or_default())?)));
+        registry.insert("rate_limit", |s| {
+            let config = s.connection.rate_limit()
+                .ok_or_else(|| anyhow!("rate_limit middleware needs a `rate_limit` table on the wire"))?;
+            Ok(Arc::new(RateLimitMiddleware::new(*config)?))
+        });
+        registry.insert("timeout", |s| {
+            let timeout_ms = s.connection.timeout_ms()
+                .ok_or_else(|| anyhow!("timeout middleware needs `timeout_ms` on the wire"))?;
+            Ok(Arc::new(TimeoutMiddleware::new(timeout_ms)))
+        });
+        registry
+    }
+
+    /// Makes `name` available to blueprints. Names are never replaced, so a plugin
+    /// cannot quietly stand in for a built-in such as `approval`.
+    pub fn register<F>(&mut self, name: &str, factory: F) -> Result<()>
+    where
+        F: Fn(&Setup) -> Result<Arc<dyn Middleware>> + Send + Sync + 'static,
+    {
+        if self.factories.contains_key(name) {
+            return Err(anyhow!("middleware '{}' is already registered", name));
+        }
+        self.insert(name, factory);
+        Ok(())
+    }
+
+    fn insert<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&Setup) -> Result<Arc<dyn Middleware>> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
 
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
+    /// Names of everything registered, built-ins included.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    /// Builds the named middleware from the settings of the wire it sits on. A
+    /// name nothing is registered under is an error: skipping it would quietly
+    /// drop a control the blueprint asked for.
+    pub fn build(&self, name: &str, connection: &Connection, services: &Services) -> Result<Arc<dyn Middleware>> {
+        let Some(factory) = self.factories.get(name) else {
+            return Err(anyhow!("unknown middleware '{}'; it is not built in, registered or a guard", name));
+        };
+        let empty = toml::Table::new();
+        let settings = match connection.settings(name) {
+            None => &empty,
+            Some(toml::Value::Table(table)) => table,
+            Some(_) => return Err(anyhow!("`{}` on the wire must be a table", name)),
+        };
+        factory(&Setup { settings, connection, services })
     }
 }
 
-pub fn get_middleware_by_name(name: &str) -> Option<Arc<dyn Middleware>> {
-    match name {
-        "logging" => Some(Arc::new(LoggingMiddleware)),
-        // "policy" => ...
-        _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn ctx() -> Arc<CallContext> {
+        Arc::new(CallContext {
+            target_component: "calendar".to_string(),
+            target_interface: "local:calendar/read".to_string(),
+            function_name: "busy".to_string(),
+            caller_component: Some("agent".to_string()),
+            param_names: Vec::new(),
+        })
+    }
+
+    /// Records when it is entered and left, around whatever is inside it.
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for Recorder {
+        fn handle(&self, _ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+            let (name, log) = (self.name, self.log.clone());
+            Box::pin(async move {
+                log.lock().unwrap().push(format!("{} in", name));
+                let result = next(params).await;
+                log.lock().unwrap().push(format!("{} out", name));
+                result
+            })
+        }
+    }
+
+    /// Answers every call itself.
+    struct Answer;
+
+    impl Middleware for Answer {
+        fn handle(&self, _ctx: &CallContext, _params: Vec<Val>, _next: Next) -> MiddlewareFuture {
+            Box::pin(async move { Ok(vec![Val::String("cached".into())]) })
+        }
+    }
+
+    /// Rejects every call.
+    struct Refuse;
+
+    impl Middleware for Refuse {
+        fn handle(&self, _ctx: &CallContext, _params: Vec<Val>, _next: Next) -> MiddlewareFuture {
+            Box::pin(async move { Err(anyhow!("refused")) })
+        }
+    }
+
+    /// The real call: logs that it ran and echoes its arguments.
+    fn terminal(log: &Arc<Mutex<Vec<String>>>) -> Next {
+        let log = log.clone();
+        Box::new(move |params| {
+            log.lock().unwrap().push("provider".to_string());
+            Box::pin(async move { Ok(params) })
+        })
+    }
+
+    fn recorder(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> Arc<dyn Middleware> {
+        Arc::new(Recorder { name, log: log.clone() })
+    }
+
+    #[tokio::test]
+    async fn layers_wrap_the_call_outermost_first() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let chain = Chain::new(vec![recorder("outer", &log), recorder("inner", &log)]);
+        let results = chain.call(ctx(), vec![Val::U32(7)], terminal(&log)).await.unwrap();
+        assert!(matches!(results.as_slice(), [Val::U32(7)]));
+        assert_eq!(*log.lock().unwrap(), ["outer in", "inner in", "provider", "inner out", "outer out"]);
+    }
+
+    #[tokio::test]
+    async fn an_empty_chain_makes_the_call() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let results = Chain::default().call(ctx(), vec![Val::Bool(true)], terminal(&log)).await.unwrap();
+        assert!(matches!(results.as_slice(), [Val::Bool(true)]));
+        assert_eq!(*log.lock().unwrap(), ["provider"]);
+    }
+
+    #[tokio::test]
+    async fn a_layer_can_answer_without_calling_next() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let chain = Chain::new(vec![recorder("outer", &log), Arc::new(Answer), recorder("inner", &log)]);
+        let results = chain.call(ctx(), vec![Val::U32(7)], terminal(&log)).await.unwrap();
+        assert!(matches!(results.as_slice(), [Val::String(s)] if &**s == "cached"));
+        assert_eq!(*log.lock().unwrap(), ["outer in", "outer out"]);
+    }
+
+    #[tokio::test]
+    async fn a_layer_can_reject_the_call() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let chain = Chain::new(vec![recorder("outer", &log), Arc::new(Refuse), recorder("inner", &log)]);
+        let e = chain.call(ctx(), vec![Val::U32(7)], terminal(&log)).await.unwrap_err();
+        assert_eq!(e.to_string(), "refused");
+        assert_eq!(*log.lock().unwrap(), ["outer in", "outer out"]);
+    }
+
+    fn services() -> Services {
+        Services { approver: Arc::new(crate::approval::TerminalApprover), audit_log: None }
+    }
+
+    #[test]
+    fn registered_names_are_never_replaced() {
+        let mut registry = Registry::builtin();
+        registry.register("acme", |_| Ok(Arc::new(NoOpMiddleware))).unwrap();
+        assert!(registry.names().any(|name| name == "acme"));
+
+        let e = registry.register("acme", |_| Ok(Arc::new(NoOpMiddleware))).err().unwrap();
+        assert_eq!(e.to_string(), "middleware 'acme' is already registered");
+        let e = registry.register("approval", |_| Ok(Arc::new(NoOpMiddleware))).err().unwrap();
+        assert_eq!(e.to_string(), "middleware 'approval' is already registered");
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let registry = Registry::builtin();
+        let connection = Connection::through("calendar", &["acme"]);
+        assert!(registry.build("noop", &connection, &services()).is_ok());
+        let e = registry.build("acme", &connection, &services()).err().unwrap();
+        assert!(e.to_string().contains("unknown middleware 'acme'"));
     }
 }
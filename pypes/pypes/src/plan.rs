@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Result};
+use pypes_analyser::Blueprint;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// The order to instantiate components in: every provider before the consumers
+/// wired to it. Fails, before anything is instantiated, on wires naming unknown
+/// components, host wires to anything but WASI (the only interfaces the host
+/// links), imports no wire provides (WASI aside), and wiring cycles. `imports` holds each component's `ComponentTypes::linked_imports`.
+pub fn instantiation_order(blueprint: &Blueprint, imports: &HashMap<String, Vec<String>>) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    // Consumer -> the components it imports from.
+    let mut providers: BTreeMap<&str, BTreeSet<&str>> =
+        blueprint.components.keys().map(|name| (name.as_str(), BTreeSet::new())).collect();
+
+    let mut wiring: Vec<_> = blueprint.wiring.iter().collect();
+    wiring.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    for (consumer_key, connection) in wiring {
+        let consumer = consumer_key.split('.').next().unwrap_or(consumer_key);
+        let provider_key = connection.provider();
+        let provider = provider_key.split('.').next().unwrap_or(provider_key);
+        if !blueprint.components.contains_key(consumer) {
+            problems.push(format!("wire '{}' is for '{}', which is not a component", consumer_key, consumer));
+            continue;
+        }
+        if provider == "host" {
+            if !provider_key.starts_with("host.wasi:") {
+                problems.push(format!(
+                    "wire '{}' -> '{}' names the host, which provides only wasi:* interfaces",
+                    consumer_key, provider_key
+                ));
+            }
+            continue;
+        }
+        if !blueprint.components.contains_key(provider) {
+            problems.push(format!(
+                "wire '{}' -> '{}' names provider '{}', which is not a component",
+                consumer_key, provider_key, provider
+            ));
+            continue;
+        }
+        if let Some(set) = providers.get_mut(consumer) {
+            set.insert(provider);
+        }
+    }
+
+    let mut names: Vec<&String> = imports.keys().collect();
+    names.sort();
+    for name in names {
+        for import in &imports[name] {
+            if import.starts_with("wasi:") || blueprint.wiring.contains_key(&format!("{}.{}", name, import)) {
+                continue;
+            }
+            problems.push(format!("{} imports {} but no wire provides it", name, import));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(anyhow!("Cannot instantiate this blueprint:\n  {}", problems.join("\n  ")));
+    }
+
+    // Kahn's algorithm, taking ready components in name order so runs are repeatable.
+    let mut order = Vec::new();
+    let mut remaining = providers;
+    loop {
+        let ready: Vec<&str> = remaining.iter()
+            .filter(|(_, deps)| deps.iter().all(|d| !remaining.contains_key(d)))
+            .map(|(&name, _)| name)
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for name in ready {
+            remaining.remove(name);
+            order.push(name.to_string());
+        }
+    }
+
+    if let Some((&start, _)) = remaining.iter().next() {
+        // Every component left waits on another one left, so following any
+        // dependency from `start` must come back round.
+        let mut path = vec![start];
+        loop {
+            let current = *path.last().unwrap_or(&start);
+            let next = remaining[current].iter().find(|d| remaining.contains_key(*d)).copied().unwrap_or(start);
+            if let Some(pos) = path.iter().position(|&c| c == next) {
+                let mut cycle: Vec<&str> = path[pos..].to_vec();
+                cycle.push(next);
+                return Err(anyhow!("Wiring cycle (each imports from the next): {}", cycle.join(" -> ")));
+            }
+            path.push(next);
+        }
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blueprint(components: &[&str], wiring: &str) -> Blueprint {
+        let components: Vec<String> = components.iter().map(|c| format!("{} = \"{}.wasm\"", c, c)).collect();
+        toml::from_str(&format!("[components]\n{}\n[wiring]\n{}", components.join("\n"), wiring)).unwrap()
+    }
+
+    fn imports(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        entries.iter()
+            .map(|(name, imports)| (name.to_string(), imports.iter().map(|i| i.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn providers_come_first() {
+        let blueprint = blueprint(&["agent", "calendar", "llm"], r#"
+            "agent.local:cal/read" = "calendar.local:cal/read"
+            "agent.local:llm/chat" = "llm.local:llm/chat"
+            "calendar.local:llm/chat" = "llm.local:llm/chat"
+            "calendar.wasi:filesystem/types" = "host.wasi:filesystem/types"
+        "#);
+        let imports = imports(&[
+            ("agent", &["local:cal/read", "local:llm/chat"]),
+            ("calendar", &["local:llm/chat", "wasi:filesystem/types"]),
+            ("llm", &["wasi:cli/stdout"]),
+        ]);
+        assert_eq!(instantiation_order(&blueprint, &imports).unwrap(), ["llm", "calendar", "agent"]);
+    }
+
+    #[test]
+    fn cycles_are_named() {
+        let blueprint = blueprint(&["a", "b", "c"], r#"
+            "a.x:y/b" = "b.x:y/b"
+            "b.x:y/c" = "c.x:y/c"
+            "c.x:y/a" = "a.x:y/a"
+        "#);
+        let e = instantiation_order(&blueprint, &HashMap::new()).unwrap_err();
+        assert_eq!(e.to_string(), "Wiring cycle (each imports from the next): a -> b -> c -> a");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let blueprint = blueprint(&["agent"], r#"
+            "agent.local:cal/read" = "calendar.local:cal/read"
+            "ghost.local:cal/read" = "host.local:cal/read"
+            "agent.local:cal/write" = "host.local:cal/write"
+        "#);
+        let imports = imports(&[("agent", &["local:cal/read", "local:cal/write", "local:cal/list", "wasi:io/streams"])]);
+        let e = instantiation_order(&blueprint, &imports).unwrap_err().to_string();
+        assert_eq!(e, "Cannot instantiate this blueprint:\n  \
+            wire 'agent.local:cal/read' -> 'calendar.local:cal/read' names provider 'calendar', which is not a component\n  \
+            wire 'agent.local:cal/write' -> 'host.local:cal/write' names the host, which provides only wasi:* interfaces\n  \
+            wire 'ghost.local:cal/read' is for 'ghost', which is not a component\n  \
+            agent imports local:cal/list but no wire provides it");
+    }
+}
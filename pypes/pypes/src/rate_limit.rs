@@ -0,0 +1,116 @@
+use anyhow::{anyhow, Result};
+use pypes_analyser::{OnLimit, RateLimitConfig};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+
+/// Token bucket shared by every function on a wire: each call takes a token,
+/// tokens come back at `per_second`, and at most `burst` are saved up. A call
+/// finding the bucket empty is rejected, or held until a token is free.
+pub struct RateLimitMiddleware {
+    config: RateLimitConfig,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(config: RateLimitConfig) -> Result<Self> {
+        if config.per_second.is_nan() || config.per_second <= 0.0 || config.burst == 0 {
+            return Err(anyhow!("rate_limit needs per_second > 0 and burst >= 1"));
+        }
+        let bucket = Bucket { tokens: config.burst as f64, refilled: Instant::now() };
+        Ok(Self { config, bucket: Arc::new(Mutex::new(bucket)) })
+    }
+}
+
+/// Takes a token, or says how long until one is free.
+fn take(bucket: &Mutex<Bucket>, config: &RateLimitConfig) -> Result<(), Duration> {
+    let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    let earned = now.duration_since(bucket.refilled).as_secs_f64() * config.per_second;
+    bucket.tokens = (bucket.tokens + earned).min(config.burst as f64);
+    bucket.refilled = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / config.per_second))
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let config = self.config;
+        match (take(&self.bucket, &config), config.on_limit) {
+            (Ok(()), _) => next(params),
+            (Err(_), OnLimit::Reject) => {
+                let e = anyhow!(
+                    "Rate limit: call to {} is over {} calls/s (burst {}); call rejected",
+                    ctx, config.per_second, config.burst
+                );
+                Box::pin(async move { Err(e) })
+            }
+            (Err(wait), OnLimit::Wait) => {
+                println!("   ⏳ [rate_limit] Holding call to {} for {}ms", ctx, wait.as_millis());
+                let bucket = self.bucket.clone();
+                Box::pin(async move {
+                    let mut wait = wait;
+                    // Another call may take the token first, so check again after each wait.
+                    loop {
+                        tokio::time::sleep(wait).await;
+                        match take(&bucket, &config) {
+                            Ok(()) => return next(params).await,
+                            Err(again) => wait = again,
+                        }
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(per_second: f64, burst: u32) -> RateLimitConfig {
+        RateLimitConfig { per_second, burst, on_limit: OnLimit::Reject }
+    }
+
+    #[test]
+    fn burst_calls_go_through_back_to_back() {
+        let limiter = RateLimitMiddleware::new(config(1.0, 3)).unwrap();
+        for _ in 0..3 {
+            assert!(take(&limiter.bucket, &limiter.config).is_ok());
+        }
+        let wait = take(&limiter.bucket, &limiter.config).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn tokens_refill_at_the_rate_up_to_the_burst() {
+        let config = config(2.0, 2);
+        let bucket = Mutex::new(Bucket { tokens: 0.0, refilled: Instant::now() - Duration::from_secs(10) });
+        // Twenty tokens earned, but only two kept.
+        assert!(take(&bucket, &config).is_ok());
+        assert!(take(&bucket, &config).is_ok());
+        assert!(take(&bucket, &config).is_err());
+
+        let bucket = Mutex::new(Bucket { tokens: 0.5, refilled: Instant::now() });
+        let wait = take(&bucket, &config).unwrap_err();
+        assert!(wait > Duration::from_millis(200) && wait <= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn rejects_limits_that_never_refill() {
+        assert!(RateLimitMiddleware::new(config(0.0, 1)).is_err());
+        assert!(RateLimitMiddleware::new(config(f64::NAN, 1)).is_err());
+        assert!(RateLimitMiddleware::new(config(1.0, 0)).is_err());
+    }
+}
@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use wasmtime::component::{InstancePre, Resource, ResourceAny, ResourceImportIndex, ResourceType, Type, Val};
+use wasmtime::{Store, StoreContextMut};
+
+use crate::HostState;
+
+/// What a provider's resource becomes in the consumer it is handed to. Each
+/// component has its own store, so a handle cannot move between them; the
+/// consumer gets one of these instead, and the host maps it back to the
+/// provider's handle whenever it is passed over the wire again.
+pub struct ProxiedResource;
+
+struct Entry {
+    provider: String,
+    /// The provider's name for the handle's resource type.
+    resource: String,
+    handle: ResourceAny,
+}
+
+#[derive(Default)]
+struct State {
+    next_rep: u32,
+    entries: HashMap<u32, Entry>,
+    // Provider handles the consumer has dropped, by provider, dropped in turn on
+    // the next call into it (the consumer's drop cannot wait for its store).
+    released: HashMap<String, Vec<ResourceAny>>,
+}
+
+/// Provider handles held by consumers, keyed by the rep of the consumer's
+/// `ProxiedResource`. A handle only ever goes back to the component that made it.
+#[derive(Default)]
+pub struct ResourceBridge {
+    state: Mutex<State>,
+}
+
+impl ResourceBridge {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Registers a `resource` handle `provider` gave out; returns the rep for the
+    /// consumer's.
+    fn hand_out(&self, provider: &str, resource: &str, handle: ResourceAny) -> u32 {
+        let mut state = self.state();
+        let rep = state.next_rep;
+        state.next_rep += 1;
+        state.entries.insert(rep, Entry { provider: provider.to_string(), resource: resource.to_string(), handle });
+        rep
+    }
+
+    /// The provider handle `rep` stands for, if it is of the `expected` resource
+    /// type. An owned handle goes back to the provider for good; a borrowed one is
+    /// lent for the call.
+    fn redeem(&self, rep: u32, provider: &str, expected: (ResourceType, &str), owned: bool) -> Result<ResourceAny> {
+        let mut state = self.state();
+        let entry = state.entries.get(&rep).ok_or_else(|| anyhow!("unknown resource handle {}", rep))?;
+        if entry.provider != provider {
+            return Err(anyhow!("a resource from '{}' cannot be passed to '{}'", entry.provider, provider));
+        }
+        // Every consumer import is a `ProxiedResource`, so only the provider's
+        // handle tells a `cursor` from a `session`.
+        if entry.handle.ty() != expected.0 {
+            return Err(anyhow!(
+                "a '{}' handle cannot be passed where '{}' expects a '{}'",
+                entry.resource, provider, expected.1
+            ));
+        }
+        let handle = entry.handle;
+        if owned {
+            state.entries.remove(&rep);
+        }
+        Ok(handle)
+    }
+
+    /// The consumer dropped its handle; the provider's is dropped on the next
+    /// call into the provider.
+    pub fn release(&self, rep: u32) {
+        let mut state = self.state();
+        if let Some(entry) = state.entries.remove(&rep) {
+            state.released.entry(entry.provider).or_default().push(entry.handle);
+        }
+    }
+
+    /// Drops a handle `provider` returned that never reached a consumer.
+    fn discard(&self, provider: &str, handle: ResourceAny) {
+        if handle.owned() {
+            self.state().released.entry(provider.to_string()).or_default().push(handle);
+        }
+    }
+
+    /// Drops the handles released since the last call into `provider`.
+    pub async fn drop_released(&self, provider: &str, store: &mut Store<HostState>) -> Result<()> {
+        let released = self.state().released.remove(provider).unwrap_or_default();
+        for handle in released {
+            handle.resource_drop_async(&mut *store).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A resource type of the interface on a wire: the provider's type, and the
+/// consumer's `ProxiedResource` import that stands for it.
+pub struct WireResource {
+    pub name: String,
+    pub ty: ResourceType,
+    pub import: ResourceImportIndex,
+}
+
+/// One side of a wire: the consumer calling through it and where the provider's
+/// resources land in it.
+pub struct WireResources<'a> {
+    pub bridge: &'a ResourceBridge,
+    pub provider: &'a str,
+    /// The consumer's instantiation, set once it has been instantiated.
+    pub consumer: Option<&'a InstancePre<HostState>>,
+    /// The interface's resource types, one import each.
+    pub resources: &'a [WireResource],
+    /// A data diode: no handle crosses in either direction.
+    pub diode: bool,
+}
+
+impl WireResources<'_> {
+    /// `val`, from the consumer, with its handles swapped for the provider's, as
+    /// a value of the provider's parameter type `ty`.
+    pub fn to_provider(&self, ctx: &mut StoreContextMut<'_, HostState>, val: &Val, ty: &Type) -> Result<Val> {
+        if !carries_resource(val) {
+            return Ok(val.clone());
+        }
+        if self.diode {
+            return Err(anyhow!("a resource handle cannot cross a diode wire"));
+        }
+        Ok(match (val, ty) {
+            (Val::Resource(handle), Type::Own(expected) | Type::Borrow(expected)) => {
+                if handle.ty() != ResourceType::host::<ProxiedResource>() {
+                    return Err(anyhow!("only resources from '{}' can be passed to it", self.provider));
+                }
+                let name = self.resources.iter().find(|r| r.ty == *expected).map_or("resource", |r| r.name.as_str());
+                let owned = handle.owned();
+                let resource: Resource<ProxiedResource> = handle.try_into_resource(&mut *ctx)?;
+                Val::Resource(self.bridge.redeem(resource.rep(), self.provider, (*expected, name), owned)?)
+            }
+            (Val::List(list), Type::List(list_ty)) => {
+                let element = list_ty.ty();
+                let values = list.iter().map(|v| self.to_provider(ctx, v, &element)).collect::<Result<Vec<_>>>()?;
+                list_ty.new_val(values.into_boxed_slice())?
+            }
+            (Val::Record(record), Type::Record(record_ty)) => {
+                let mut values = Vec::new();
+                for ((name, v), field) in record.fields().zip(record_ty.fields()) {
+                    values.push((name, self.to_provider(ctx, v, &field.ty)?));
+                }
+                record_ty.new_val(values)?
+            }
+            (Val::Tuple(tuple), Type::Tuple(tuple_ty)) => {
+                let values = tuple.values().iter().zip(tuple_ty.types())
+                    .map(|(v, ty)| self.to_provider(ctx, v, &ty))
+                    .collect::<Result<Vec<_>>>()?;
+                tuple_ty.new_val(values.into_boxed_slice())?
+            }
+            (Val::Variant(variant), Type::Variant(variant_ty)) => {
+                let case = variant_ty.cases().find(|c| c.name == variant.discriminant())
+                    .ok_or_else(|| anyhow!("unknown variant case '{}'", variant.discriminant()))?;
+                let payload = match (variant.payload(), &case.ty) {
+                    (Some(v), Some(ty)) => Some(self.to_provider(ctx, v, ty)?),
+                    _ => None,
+                };
+                variant_ty.new_val(variant.discriminant(), payload)?
+            }
+            (Val::Option(option), Type::Option(option_ty)) => {
+                let value = option.value().map(|v| self.to_provider(ctx, v, &option_ty.ty())).transpose()?;
+                option_ty.new_val(value)?
+            }
+            (Val::Result(result), Type::Result(result_ty)) => {
+                let value = match result.value() {
+                    Ok(v) => Ok(v.zip(result_ty.ok()).map(|(v, ty)| self.to_provider(ctx, v, &ty)).transpose()?),
+                    Err(v) => Err(v.zip(result_ty.err()).map(|(v, ty)| self.to_provider(ctx, v, &ty)).transpose()?),
+                };
+                result_ty.new_val(value)?
+            }
+            (val, _) => return Err(anyhow!("{:?} does not fit the provider's parameter type", val)),
+        })
+    }
+
+    /// `val`, from the provider, with its handle swapped for one the consumer can
+    /// hold. Only handles returned as results themselves can cross: the host has
+    /// no way to build the consumer's version of a record, list, option or
+    /// result with a handle inside. On failure the provider's handles in `val`
+    /// are dropped.
+    pub fn to_consumer(&self, ctx: &mut StoreContextMut<'_, HostState>, val: Val) -> Result<Val> {
+        if self.diode && carries_resource(&val) {
+            self.discard(&val);
+            return Err(anyhow!("a resource handle cannot cross a diode wire"));
+        }
+        match val {
+            Val::Resource(handle) => {
+                let import = self.resources.iter().find(|r| r.ty == handle.ty());
+                let (Some(consumer), Some(import)) = (self.consumer, import) else {
+                    self.bridge.discard(self.provider, handle);
+                    return Err(anyhow!("'{}' returned a resource the consumer has no import for", self.provider));
+                };
+                let rep = self.bridge.hand_out(self.provider, &import.name, handle);
+                let resource = Resource::<ProxiedResource>::new_own(rep);
+                Ok(Val::Resource(ResourceAny::try_from_resource(resource, &mut *ctx, consumer, import.import)?))
+            }
+            val if carries_resource(&val) => {
+                self.discard(&val);
+                Err(anyhow!(
+                    "'{}' returned a resource inside a {}; only resources returned directly can cross a wire",
+                    self.provider, composite_kind(&val)
+                ))
+            }
+            val => Ok(val),
+        }
+    }
+
+    /// Gives up the consumer's borrowed handles in `args`, which a call has to do
+    /// before it returns. `to_provider` has already swapped them.
+    pub async fn end_borrows(ctx: &mut StoreContextMut<'_, HostState>, args: &[Val]) -> Result<()> {
+        let mut handles = Vec::new();
+        args.iter().for_each(|arg| collect_resources(arg, &mut handles));
+        for handle in handles.into_iter().filter(|handle| !handle.owned()) {
+            handle.resource_drop_async(&mut *ctx).await?;
+        }
+        Ok(())
+    }
+
+    /// Drops the provider's handles in `val`, which will not reach the consumer.
+    pub fn discard(&self, val: &Val) {
+        let mut handles = Vec::new();
+        collect_resources(val, &mut handles);
+        for handle in handles {
+            self.bridge.discard(self.provider, handle);
+        }
+    }
+}
+
+/// Whether `val` is, or holds, a resource handle.
+pub fn carries_resource(val: &Val) -> bool {
+    let mut handles = Vec::new();
+    collect_resources(val, &mut handles);
+    !handles.is_empty()
+}
+
+fn composite_kind(val: &Val) -> &'static str {
+    match val {
+        Val::List(_) => "list",
+        Val::Record(_) => "record",
+        Val::Tuple(_) => "tuple",
+        Val::Variant(_) => "variant",
+        Val::Option(_) => "option",
+        Val::Result(_) => "result",
+        _ => "value",
+    }
+}
+
+fn collect_resources(val: &Val, out: &mut Vec<ResourceAny>) {
+    match val {
+        Val::Resource(handle) => out.push(*handle),
+        Val::List(list) => list.iter().for_each(|v| collect_resources(v, out)),
+        Val::Record(record) => record.fields().for_each(|(_, v)| collect_resources(v, out)),
+        Val::Tuple(tuple) => tuple.values().iter().for_each(|v| collect_resources(v, out)),
+        Val::Variant(variant) => variant.payload().into_iter().for_each(|v| collect_resources(v, out)),
+        Val::Option(option) => option.value().into_iter().for_each(|v| collect_resources(v, out)),
+        Val::Result(result) => match result.value() {
+            Ok(v) | Err(v) => v.into_iter().for_each(|v| collect_resources(v, out)),
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::HostGrants;
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{AsContextMut, Config, Engine};
+
+    /// Exports test:res/counters, whose `make` and `open` hand out a counter and
+    /// a cursor.
+    const PROVIDER: &str = r#"
+        (component
+            (type $counter (resource (rep i32)))
+            (type $cursor (resource (rep i32)))
+            (core func $counter_new (canon resource.new $counter))
+            (core func $cursor_new (canon resource.new $cursor))
+            (core module $m
+                (import "r" "new" (func $new (param i32) (result i32)))
+                (import "r" "cnew" (func $cnew (param i32) (result i32)))
+                (func (export "make") (result i32) i32.const 1 call $new)
+                (func (export "open") (result i32) i32.const 7 call $cnew)
+            )
+            (core instance $r (export "new" (func $counter_new)) (export "cnew" (func $cursor_new)))
+            (core instance $i (instantiate $m (with "r" (instance $r))))
+            (func $make (result (own $counter)) (canon lift (core func $i "make")))
+            (func $open (result (own $cursor)) (canon lift (core func $i "open")))
+            (component $shim
+                (import "t-counter" (type $c (sub resource)))
+                (import "t-cursor" (type $k (sub resource)))
+                (import "f-make" (func $make (result (own $c))))
+                (import "f-open" (func $open (result (own $k))))
+                (export $ce "counter" (type $c))
+                (export $ke "cursor" (type $k))
+                (export "make" (func $make) (func (result (own $ce))))
+                (export "open" (func $open) (func (result (own $ke))))
+            )
+            (instance $inst (instantiate $shim
+                (with "t-counter" (type $counter))
+                (with "t-cursor" (type $cursor))
+                (with "f-make" (func $make))
+                (with "f-open" (func $open))
+            ))
+            (export "test:res/counters" (instance $inst))
+        )
+    "#;
+
+    /// Holds test:res/counters handles, without calling anything.
+    const CONSUMER: &str = r#"
+        (component
+            (import "test:res/counters" (instance
+                (export "counter" (type (sub resource)))
+                (export "cursor" (type (sub resource)))
+            ))
+        )
+    "#;
+
+    /// Both sides of a wire, with a counter and a cursor the provider made, and
+    /// the consumer's store.
+    struct Fixture {
+        bridge: ResourceBridge,
+        pre: InstancePre<HostState>,
+        resources: Vec<WireResource>,
+        counter: ResourceAny,
+        cursor: ResourceAny,
+    }
+
+    impl Fixture {
+        async fn new() -> Result<(Self, Store<HostState>)> {
+            let mut config = Config::new();
+            config.async_support(true);
+            let engine = Engine::new(&config)?;
+            let state = |name| Ok::<_, anyhow::Error>(HostState::new(name, HostGrants::default().build_ctx()?, None));
+
+            let mut provider = Store::new(&engine, state("provider")?);
+            let instance = Linker::new(&engine).instantiate_async(&mut provider, &Component::new(&engine, PROVIDER)?).await?;
+            let mut handles = Vec::new();
+            for name in ["make", "open"] {
+                let func = instance.exports(&mut provider).instance("test:res/counters").and_then(|mut i| i.func(name)).unwrap();
+                let mut results = [Val::Bool(false)];
+                func.call_async(&mut provider, &[], &mut results).await?;
+                func.post_return_async(&mut provider).await?;
+                let [Val::Resource(handle)] = results else { unreachable!() };
+                handles.push(handle);
+            }
+
+            let mut linker = Linker::new(&engine);
+            let mut counters = linker.instance("test:res/counters")?;
+            let mut resources = Vec::new();
+            for name in ["counter", "cursor"] {
+                let ty = instance.exports(&mut provider).instance("test:res/counters").and_then(|mut i| i.resource(name)).unwrap();
+                let import = counters.resource(name, ResourceType::host::<ProxiedResource>(), |_, _| Ok(()))?;
+                resources.push(WireResource { name: name.to_string(), ty, import });
+            }
+            let pre = linker.instantiate_pre(&Component::new(&engine, CONSUMER)?)?;
+            let fixture = Self {
+                bridge: ResourceBridge::default(),
+                pre,
+                resources,
+                counter: handles[0],
+                cursor: handles[1],
+            };
+            Ok((fixture, Store::new(&engine, state("consumer")?)))
+        }
+
+        fn wire(&self, diode: bool) -> WireResources<'_> {
+            WireResources { bridge: &self.bridge, provider: "provider", consumer: Some(&self.pre), resources: &self.resources, diode }
+        }
+
+        fn own(&self, name: &str) -> Type {
+            Type::Own(self.resources.iter().find(|r| r.name == name).unwrap().ty)
+        }
+    }
+
+    #[tokio::test]
+    async fn handles_round_trip_to_the_provider() {
+        let (f, mut consumer) = Fixture::new().await.unwrap();
+        let held = f.wire(false).to_consumer(&mut consumer.as_context_mut(), Val::Resource(f.counter)).unwrap();
+        let Val::Resource(stand_in) = &held else { panic!("{:?}", held) };
+        assert_eq!(stand_in.ty(), ResourceType::host::<ProxiedResource>());
+
+        let counter = f.own("counter");
+        let back = f.wire(false).to_provider(&mut consumer.as_context_mut(), &held, &counter).unwrap();
+        assert_eq!(back, Val::Resource(f.counter));
+        // An owned handle went back for good.
+        assert!(f.bridge.state().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn handles_only_go_back_as_their_own_type() {
+        let (f, mut consumer) = Fixture::new().await.unwrap();
+        let held = f.wire(false).to_consumer(&mut consumer.as_context_mut(), Val::Resource(f.cursor)).unwrap();
+        let counter = f.own("counter");
+        let error = f.wire(false).to_provider(&mut consumer.as_context_mut(), &held, &counter).unwrap_err();
+        assert_eq!(error.to_string(), "a 'cursor' handle cannot be passed where 'provider' expects a 'counter'");
+        // The consumer still holds it.
+        assert_eq!(f.bridge.state().entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn diodes_refuse_handles_both_ways() {
+        let (f, mut consumer) = Fixture::new().await.unwrap();
+        let error = f.wire(true).to_consumer(&mut consumer.as_context_mut(), Val::Resource(f.counter)).unwrap_err();
+        assert!(error.to_string().contains("diode"));
+        // The refused handle is dropped on the next call into the provider.
+        assert_eq!(f.bridge.state().released["provider"], [f.counter]);
+
+        let held = f.wire(false).to_consumer(&mut consumer.as_context_mut(), Val::Resource(f.cursor)).unwrap();
+        let cursor = f.own("cursor");
+        let error = f.wire(true).to_provider(&mut consumer.as_context_mut(), &held, &cursor).unwrap_err();
+        assert!(error.to_string().contains("diode"));
+        assert!(f.wire(true).to_provider(&mut consumer.as_context_mut(), &Val::U32(1), &Type::U32).is_ok());
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Context, Result};
+use pypes_analyser::{AccessMode, Blueprint, Connection, EnvGrant, HttpGrant, Preopen};
+use std::path::PathBuf;
+use wasmtime::component::{Linker, Resource};
+use wasmtime::StoreContextMut;
+use wasmtime_wasi::preview2::bindings::clocks::wall_clock::Datetime;
+use wasmtime_wasi::preview2::{Deterministic, DirPerms, FilePerms, Pollable, WasiCtx, WasiCtxBuilder};
+use wasmtime_wasi::sync::{ambient_authority, Dir};
+
+use crate::HostState;
+
+const WALL_CLOCK: &str = "wasi:clocks/wall-clock@0.2.0";
+const MONOTONIC_CLOCK: &str = "wasi:clocks/monotonic-clock@0.2.0";
+
+/// The host capabilities granted to a single component.
+///
+/// Built from the blueprint's `"<component>.<import>" = "host.<interface>"` wires,
+/// so a component only gets what `pypes_analyser::verify` saw it being given.
+#[derive(Debug, Default, Clone)]
+pub struct HostGrants {
+    pub filesystem: bool,
+    pub sockets: bool,
+    pub environment: bool,
+    pub clocks: bool,
+    pub random: bool,
+    pub stdin: bool,
+    pub preopens: Vec<Preopen>,
+    pub http: Option<HttpGrant>,
+    pub env: EnvGrant,
+}
+
+impl HostGrants {
+    /// Fails on a `preopen`, `http` or `env` setting on a wire to any other interface
+    /// than its own, and on grants that would overlap: two `http` allowlists or
+    /// `env` grants, or two directories at the same guest path.
+    pub fn for_component(blueprint: &Blueprint, component: &str) -> Result<Self> {
+        let mut grants = Self::default();
+        let mut env_granted = false;
+        for (interface, connection) in host_wires(blueprint, component) {
+            grants.grant(interface);
+            let unversioned = interface.split('@').next().unwrap_or(interface);
+            if let Some(preopen) = connection.preopen() {
+                if !unversioned.starts_with("wasi:filesystem/") {
+                    return Err(anyhow!("`preopen` on the host.{} wire of '{}' only applies to wasi:filesystem", interface, component));
+                }
+                if grants.preopens.iter().any(|p| p.guest_path == preopen.guest_path) {
+                    return Err(anyhow!("'{}' has more than one preopen at '{}'", component, preopen.guest_path));
+                }
+                grants.preopens.push(preopen.clone());
+            }
+            if let Some(http) = connection.http() {
+                if unversioned != "wasi:http/outgoing-handler" {
+                    return Err(anyhow!("`http` on the host.{} wire of '{}' only applies to wasi:http/outgoing-handler", interface, component));
+                }
+                if grants.http.is_some() {
+                    return Err(anyhow!("'{}' has `http` grants on more than one wire", component));
+                }
+                grants.http = Some(http.clone());
+            }
+            if let Some(env) = connection.env() {
+                if unversioned != "wasi:cli/environment" {
+                    return Err(anyhow!("`env` on the host.{} wire of '{}' only applies to wasi:cli/environment", interface, component));
+                }
+                if env_granted {
+                    return Err(anyhow!("'{}' has `env` grants on more than one wire", component));
+                }
+                env_granted = true;
+                grants.env = env.clone();
+            }
+        }
+        Ok(grants)
+    }
+
+    fn grant(&mut self, interface: &str) {
+        // "wasi:cli/environment@0.2.0" -> ("wasi:cli", "environment")
+        let interface = interface.split('@').next().unwrap_or(interface);
+        let (package, name) = interface.split_once('/').unwrap_or((interface, ""));
+        match package {
+            "wasi:filesystem" => self.filesystem = true,
+            "wasi:sockets" => self.sockets = true,
+            "wasi:clocks" => self.clocks = true,
+            "wasi:random" => self.random = true,
+            "wasi:cli" => match name {
+                "environment" => self.environment = true,
+                "stdin" => self.stdin = true,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Builds the WASI context for this component.
+    ///
+    /// Every WASI interface is still linked (components built with the preview1
+    /// adapter import all of them), but anything not granted is inert: no network
+    /// addresses, no environment and a constant RNG. Clocks trap, see
+    /// `add_to_linker`. Stdout/stderr are always inherited so components can log to
+    /// the operator. Only the variables named by the `env` grant and the
+    /// directories named by `preopen` grants are visible, the latter only with the
+    /// requested mode.
+    pub fn build_ctx(&self) -> Result<WasiCtx> {
+        let mut builder = WasiCtxBuilder::new();
+        builder.inherit_stdout().inherit_stderr();
+
+        if self.stdin {
+            builder.inherit_stdin();
+        }
+        if self.environment {
+            let inherited = self.env.inherit.iter()
+                .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)));
+            let set = self.env.set.iter().map(|(name, value)| (name.clone(), value.clone()));
+            builder.envs(&inherited.chain(set).collect::<Vec<_>>());
+        }
+        if self.sockets {
+            builder.inherit_network().allow_ip_name_lookup(true);
+        }
+        if !self.random {
+            builder
+                .secure_random(Deterministic::new(vec![0]))
+                .insecure_random(Deterministic::new(vec![0]))
+                .insecure_random_seed(0);
+        }
+        if self.filesystem {
+            for preopen in &self.preopens {
+                let host_path = expand_home(&preopen.host_path);
+                let dir = Dir::open_ambient_dir(&host_path, ambient_authority())
+                    .with_context(|| format!("Failed to preopen {:?} for '{}'", host_path, preopen.guest_path))?;
+                let (dir_perms, file_perms) = match preopen.mode {
+                    AccessMode::ReadOnly => (DirPerms::READ, FilePerms::READ),
+                    AccessMode::ReadWrite => (DirPerms::all(), FilePerms::all()),
+                };
+                builder.preopened_dir(dir, dir_perms, file_perms, &preopen.guest_path);
+            }
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Links WASI into this component's linker. Without a `wasi:clocks` grant every
+    /// clock call traps: a clock that never moves would hang a guest that sleeps or
+    /// polls it.
+    pub fn add_to_linker(&self, linker: &mut Linker<HostState>) -> Result<()> {
+        wasmtime_wasi::preview2::command::add_to_linker(linker)?;
+        if self.clocks {
+            return Ok(());
+        }
+        linker.allow_shadowing(true);
+        let mut wall_clock = linker.instance(WALL_CLOCK)?;
+        for name in ["now", "resolution"] {
+            wall_clock.func_wrap(name, |_: StoreContextMut<'_, HostState>, (): ()| -> Result<(Datetime,)> {
+                Err(no_clocks())
+            })?;
+        }
+        let mut monotonic_clock = linker.instance(MONOTONIC_CLOCK)?;
+        for name in ["now", "resolution"] {
+            monotonic_clock.func_wrap(name, |_: StoreContextMut<'_, HostState>, (): ()| -> Result<(u64,)> {
+                Err(no_clocks())
+            })?;
+        }
+        for name in ["subscribe-instant", "subscribe-duration"] {
+            monotonic_clock.func_wrap(name, |_: StoreContextMut<'_, HostState>, (_,): (u64,)| -> Result<(Resource<Pollable>,)> {
+                Err(no_clocks())
+            })?;
+        }
+        linker.allow_shadowing(false);
+        Ok(())
+    }
+}
+
+fn no_clocks() -> anyhow::Error {
+    anyhow!("clock used without a host.wasi:clocks/* wire")
+}
+
+/// Expands a leading `~/` against `$HOME`.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// All `host.*` wires for `component`, as (host interface, connection) pairs, in
+/// wiring key order.
+pub fn host_wires<'a>(blueprint: &'a Blueprint, component: &str) -> Vec<(&'a str, &'a Connection)> {
+    let mut wiring: Vec<_> = blueprint.wiring.iter().collect();
+    wiring.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    let mut wires = Vec::new();
+    for (consumer_key, connection) in wiring {
+        let consumer = consumer_key.split('.').next().unwrap_or(consumer_key);
+        if consumer != component {
+            continue;
+        }
+        if let Some(("host", interface)) = connection.provider().split_once('.') {
+            wires.push((interface, connection));
+        }
+    }
+    wires
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::Component;
+    use wasmtime::{Config, Engine, Store};
+    use wasmtime_wasi::preview2::bindings::cli::environment::Host as _;
+    use wasmtime_wasi::preview2::bindings::filesystem::preopens::Host as _;
+    use wasmtime_wasi::preview2::bindings::random::random::Host as _;
+
+    /// Reads the monotonic clock.
+    const CLOCK_READER: &str = r#"
+        (component
+            (import "wasi:clocks/monotonic-clock@0.2.0" (instance $clock
+                (export "now" (func (result u64)))
+            ))
+            (core func $now (canon lower (func $clock "now")))
+            (core module $m
+                (import "clock" "now" (func $now (result i64)))
+                (func (export "run") (result i64) call $now)
+            )
+            (core instance $i (instantiate $m (with "clock" (instance (export "now" (func $now))))))
+            (func (export "run") (result u64) (canon lift (core func $i "run")))
+        )
+    "#;
+
+    fn grants(wiring: &str) -> Result<HostGrants> {
+        let blueprint: Blueprint = toml::from_str(&format!("[components]\nagent = \"agent.wasm\"\n[wiring]\n{}", wiring)).unwrap();
+        HostGrants::for_component(&blueprint, "agent")
+    }
+
+    fn state(grants: &HostGrants) -> HostState {
+        HostState::new("agent", grants.build_ctx().unwrap(), None)
+    }
+
+    #[test]
+    fn environment_holds_only_what_the_grant_names() {
+        let host_path = std::env::var("PATH").unwrap();
+        let granted = grants(r#"
+            "agent.wasi:cli/environment" = { provider = "host.wasi:cli/environment", env = { inherit = ["PATH", "PYPES_UNSET_VARIABLE"], set = { MODE = "demo" } } }
+        "#).unwrap();
+        assert_eq!(
+            state(&granted).get_environment().unwrap(),
+            [("PATH".to_string(), host_path), ("MODE".to_string(), "demo".to_string())]
+        );
+        let bare = grants(r#""agent.wasi:cli/environment" = "host.wasi:cli/environment""#).unwrap();
+        assert!(state(&bare).get_environment().unwrap().is_empty());
+        assert!(state(&HostGrants::default()).get_environment().unwrap().is_empty());
+    }
+
+    #[test]
+    fn host_settings_only_apply_to_their_own_interface() {
+        let env_elsewhere = grants(r#"
+            "agent.wasi:random/random" = { provider = "host.wasi:random/random", env = { inherit = ["PATH"] } }
+        "#);
+        assert!(env_elsewhere.unwrap_err().to_string().contains("only applies to wasi:cli/environment"));
+        let preopen_elsewhere = grants(r#"
+            "agent.wasi:cli/environment" = { provider = "host.wasi:cli/environment", preopen = { host_path = "/tmp", guest_path = "/tmp" } }
+        "#);
+        assert!(preopen_elsewhere.unwrap_err().to_string().contains("only applies to wasi:filesystem"));
+    }
+
+    #[test]
+    fn only_preopened_directories_are_visible() {
+        let granted = grants(r#"
+            "agent.wasi:filesystem/preopens" = { provider = "host.wasi:filesystem/preopens", preopen = { host_path = "/tmp", guest_path = "/scratch" } }
+        "#).unwrap();
+        let directories = state(&granted).get_directories().unwrap();
+        assert_eq!(directories.iter().map(|(_, path)| path.as_str()).collect::<Vec<_>>(), ["/scratch"]);
+        let bare = grants(r#""agent.wasi:filesystem/preopens" = "host.wasi:filesystem/preopens""#).unwrap();
+        assert!(state(&bare).get_directories().unwrap().is_empty());
+    }
+
+    #[test]
+    fn randomness_is_constant_without_a_grant() {
+        let mut ungranted = state(&HostGrants::default());
+        assert_eq!((ungranted.get_random_u64().unwrap(), ungranted.get_random_u64().unwrap()), (0, 0));
+        let granted = grants(r#""agent.wasi:random/random" = "host.wasi:random/random""#).unwrap();
+        let mut granted = state(&granted);
+        assert_ne!(granted.get_random_u64().unwrap(), granted.get_random_u64().unwrap());
+    }
+
+    async fn read_clock(grants: &HostGrants) -> Result<u64> {
+        let mut config = Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(&engine, CLOCK_READER)?;
+        let mut linker = Linker::new(&engine);
+        grants.add_to_linker(&mut linker)?;
+        let mut store = Store::new(&engine, state(grants));
+        let instance = linker.instantiate_async(&mut store, &component).await?;
+        let run = instance.get_typed_func::<(), (u64,)>(&mut store, "run")?;
+        let (now,) = run.call_async(&mut store, ()).await?;
+        Ok(now)
+    }
+
+    #[tokio::test]
+    async fn clocks_trap_without_a_grant() {
+        let error = read_clock(&HostGrants::default()).await.unwrap_err();
+        assert!(format!("{:?}", error).contains("without a host.wasi:clocks/* wire"), "{:?}", error);
+        let granted = grants(r#""agent.wasi:clocks/monotonic-clock" = "host.wasi:clocks/monotonic-clock""#).unwrap();
+        assert!(read_clock(&granted).await.unwrap() > 0);
+    }
+}
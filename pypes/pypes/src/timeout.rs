@@ -0,0 +1,32 @@
+use anyhow::anyhow;
+use std::time::Duration;
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+
+/// Abandons calls still running after `timeout_ms`. Guest code is interrupted at
+/// the next epoch tick (see `EPOCH_TICK` in lib.rs), so even a provider stuck in
+/// a loop is stopped. The abandoned call leaves the provider mid-call, so it is
+/// marked poisoned and later calls into it fail.
+pub struct TimeoutMiddleware {
+    limit: Duration,
+}
+
+impl TimeoutMiddleware {
+    pub fn new(timeout_ms: u64) -> Self {
+        Self { limit: Duration::from_millis(timeout_ms) }
+    }
+}
+
+impl Middleware for TimeoutMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let limit = self.limit;
+        let call = ctx.to_string();
+        Box::pin(async move {
+            match tokio::time::timeout(limit, next(params)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("Timeout: {} ran longer than {}ms; call abandoned", call, limit.as_millis())),
+            }
+        })
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Context, Result};
+use pypes_analyser::{FunctionRules, Refinement};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use wasmtime::component::Val;
+
+use crate::middleware::{CallContext, Middleware, MiddlewareFuture, Next};
+
+/// Checks arguments before the call and results after it against the wire's
+/// refinements (lengths, patterns, ranges, enum subsets). A value outside them
+/// fails the call. A `result` rule applies to a function's only result; the
+/// runtime refuses to link one onto a function with several.
+pub struct ValidateMiddleware {
+    rules: BTreeMap<String, FunctionRules>,
+    // Every `pattern` in `rules`, compiled once.
+    patterns: Arc<HashMap<String, Regex>>,
+}
+
+impl ValidateMiddleware {
+    pub fn new(rules: BTreeMap<String, FunctionRules>) -> Result<Self> {
+        let mut patterns = HashMap::new();
+        for function in rules.values() {
+            for refinement in function.args.values().chain(&function.result) {
+                compile_patterns(refinement, &mut patterns)?;
+            }
+        }
+        Ok(Self { rules, patterns: Arc::new(patterns) })
+    }
+}
+
+fn compile_patterns(refinement: &Refinement, patterns: &mut HashMap<String, Regex>) -> Result<()> {
+    if let Some(pattern) = &refinement.pattern
+        && !patterns.contains_key(pattern)
+    {
+        let regex = Regex::new(pattern).with_context(|| format!("Invalid validation pattern '{}'", pattern))?;
+        patterns.insert(pattern.clone(), regex);
+    }
+    for nested in refinement.items.as_deref().into_iter().chain(refinement.fields.values()) {
+        compile_patterns(nested, patterns)?;
+    }
+    Ok(())
+}
+
+impl Middleware for ValidateMiddleware {
+    fn handle(&self, ctx: &CallContext, params: Vec<Val>, next: Next) -> MiddlewareFuture {
+        let Some(rules) = self.rules.get(&ctx.function_name) else {
+            return next(params);
+        };
+
+        for (name, refinement) in &rules.args {
+            let Some(index) = ctx.param_names.iter().position(|p| p == name) else {
+                let e = anyhow!("Validation: {} has no parameter '{}' (is the provider's WIT available?)", ctx, name);
+                return Box::pin(async move { Err(e) });
+            };
+            let Some(value) = params.get(index) else { continue };
+            if let Err(problem) = check(refinement, value, &format!("argument '{}'", name), &self.patterns) {
+                let e = anyhow!("Validation: {}: {}", ctx, problem);
+                return Box::pin(async move { Err(e) });
+            }
+        }
+
+        let result_rule = rules.result.clone();
+        let patterns = self.patterns.clone();
+        let call = ctx.to_string();
+        let results = next(params);
+        Box::pin(async move {
+            let results = results.await?;
+            if let (Some(refinement), Some(value)) = (&result_rule, results.first()) {
+                check(refinement, value, "result", &patterns)
+                    .map_err(|problem| anyhow!("Validation: {}: {}", call, problem))?;
+            }
+            Ok(results)
+        })
+    }
+}
+
+/// Describes the first way `value` (found at `path`) breaks `refinement`.
+fn check(refinement: &Refinement, value: &Val, path: &str, patterns: &HashMap<String, Regex>) -> Result<(), String> {
+    match value {
+        Val::String(s) => {
+            let chars = s.chars().count();
+            if let Some(max) = refinement.max_length
+                && chars > max
+            {
+                return Err(format!("{} is {} characters, over max_length = {}", path, chars, max));
+            }
+            if let Some(pattern) = &refinement.pattern
+                && !patterns[pattern].is_match(s)
+            {
+                return Err(format!("{} does not match pattern '{}'", path, pattern));
+            }
+        }
+        Val::List(list) => {
+            if let Some(max) = refinement.max_length
+                && list.len() > max
+            {
+                return Err(format!("{} has {} elements, over max_length = {}", path, list.len(), max));
+            }
+            if let Some(items) = &refinement.items {
+                for (i, item) in list.iter().enumerate() {
+                    check(items, item, &format!("{}[{}]", path, i), patterns)?;
+                }
+            }
+        }
+        Val::Record(record) => {
+            for (name, field) in record.fields() {
+                if let Some(nested) = refinement.fields.get(name) {
+                    check(nested, field, &format!("{}.{}", path, name), patterns)?;
+                }
+            }
+        }
+        Val::Option(option) => {
+            if let Some(payload) = option.value() {
+                check(refinement, payload, path, patterns)?;
+            }
+        }
+        Val::Enum(e) => check_case(refinement, e.discriminant(), path)?,
+        Val::Variant(v) => check_case(refinement, v.discriminant(), path)?,
+        Val::Flags(flags) => {
+            for flag in flags.flags() {
+                check_case(refinement, flag, path)?;
+            }
+        }
+        Val::Float32(f) => check_range(refinement, *f as f64, path)?,
+        Val::Float64(f) => check_range(refinement, *f, path)?,
+        other => {
+            if let Some(n) = integer(other) {
+                check_range(refinement, n as f64, path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_case(refinement: &Refinement, case: &str, path: &str) -> Result<(), String> {
+    match &refinement.allowed {
+        Some(allowed) if !allowed.iter().any(|a| a == case) => {
+            Err(format!("{} is '{}', not one of {:?}", path, case, allowed))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_range(refinement: &Refinement, n: f64, path: &str) -> Result<(), String> {
+    if let Some(min) = refinement.min
+        && n < min as f64
+    {
+        return Err(format!("{} is {}, below min = {}", path, n, min));
+    }
+    if let Some(max) = refinement.max
+        && n > max as f64
+    {
+        return Err(format!("{} is {}, above max = {}", path, n, max));
+    }
+    Ok(())
+}
+
+fn integer(value: &Val) -> Option<i128> {
+    Some(match value {
+        Val::S8(n) => *n as i128,
+        Val::U8(n) => *n as i128,
+        Val::S16(n) => *n as i128,
+        Val::U16(n) => *n as i128,
+        Val::S32(n) => *n as i128,
+        Val::U32(n) => *n as i128,
+        Val::S64(n) => *n as i128,
+        Val::U64(n) => *n as i128,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::{Component, Linker, Type};
+    use wasmtime::{Engine, Store};
+
+    /// Parameter types of `f(names: list<string>, event: record { title: string, hour: u32 }, level: enum,
+    /// access: flags)`.
+    fn param_types() -> Vec<Type> {
+        let engine = Engine::default();
+        let component = Component::new(&engine, r#"(component
+            (core module $m
+                (memory (export "memory") 1)
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+                (func (export "f") (param i32 i32 i32 i32 i32 i32 i32)))
+            (core instance $i (instantiate $m))
+            (type $event' (record (field "title" string) (field "hour" u32)))
+            (export $event "event" (type $event'))
+            (type $level' (enum "low" "high" "max"))
+            (export $level "level" (type $level'))
+            (type $access' (flags "read" "write" "admin"))
+            (export $access "access" (type $access'))
+            (func $f (param "names" (list string)) (param "event" $event) (param "level" $level) (param "access" $access)
+                (canon lift (core func $i "f") (memory $i "memory") (realloc (func $i "realloc"))))
+            (export "f" (func $f)))"#).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine).instantiate(&mut store, &component).unwrap();
+        instance.get_func(&mut store, "f").unwrap().params(&store).to_vec()
+    }
+
+    fn rules(toml: &str) -> Refinement {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn check_with(refinement: &Refinement, value: &Val) -> Result<(), String> {
+        let mut patterns = HashMap::new();
+        compile_patterns(refinement, &mut patterns).unwrap();
+        check(refinement, value, "argument 'x'", &patterns)
+    }
+
+    #[test]
+    fn strings_are_checked_by_length_and_pattern() {
+        let refinement = rules("max_length = 5\npattern = '^[a-z]+$'");
+        assert_eq!(check_with(&refinement, &Val::String("lunch".into())), Ok(()));
+        assert_eq!(
+            check_with(&refinement, &Val::String("dinner".into())),
+            Err("argument 'x' is 6 characters, over max_length = 5".to_string())
+        );
+        assert_eq!(
+            check_with(&refinement, &Val::String("Tea".into())),
+            Err("argument 'x' does not match pattern '^[a-z]+$'".to_string())
+        );
+        // Characters, not bytes.
+        assert_eq!(check_with(&rules("max_length = 2"), &Val::String("éé".into())), Ok(()));
+    }
+
+    #[test]
+    fn numbers_are_checked_against_inclusive_bounds() {
+        let refinement = rules("min = -1\nmax = 10");
+        assert_eq!(check_with(&refinement, &Val::U8(10)), Ok(()));
+        assert_eq!(check_with(&refinement, &Val::S32(-1)), Ok(()));
+        assert_eq!(check_with(&refinement, &Val::U64(11)), Err("argument 'x' is 11, above max = 10".to_string()));
+        assert_eq!(check_with(&refinement, &Val::S8(-2)), Err("argument 'x' is -2, below min = -1".to_string()));
+        assert_eq!(check_with(&refinement, &Val::Float64(10.5)), Err("argument 'x' is 10.5, above max = 10".to_string()));
+        // Refinements for other kinds of value do not apply.
+        assert_eq!(check_with(&refinement, &Val::String("100".into())), Ok(()));
+    }
+
+    #[test]
+    fn lists_records_and_enums_are_checked_inside() {
+        let types = param_types();
+        let (Type::List(list), Type::Record(record), Type::Enum(level)) = (&types[0], &types[1], &types[2]) else {
+            panic!("unexpected parameter types {:?}", types);
+        };
+
+        let names = |names: &[&str]| {
+            list.new_val(names.iter().map(|n| Val::String((*n).into())).collect()).unwrap()
+        };
+        let refinement = rules("max_length = 2\nitems = { max_length = 3 }");
+        assert_eq!(check_with(&refinement, &names(&["ada", "bob"])), Ok(()));
+        assert_eq!(
+            check_with(&refinement, &names(&["ada", "bob", "cy"])),
+            Err("argument 'x' has 3 elements, over max_length = 2".to_string())
+        );
+        assert_eq!(
+            check_with(&refinement, &names(&["ada", "carol"])),
+            Err("argument 'x'[1] is 5 characters, over max_length = 3".to_string())
+        );
+
+        let event = |hour: u32| {
+            record.new_val([("title", Val::String("standup".into())), ("hour", Val::U32(hour))]).unwrap()
+        };
+        let refinement = rules("fields = { hour = { max = 23 } }");
+        assert_eq!(check_with(&refinement, &event(9)), Ok(()));
+        assert_eq!(check_with(&refinement, &event(24)), Err("argument 'x'.hour is 24, above max = 23".to_string()));
+
+        let refinement = rules("allowed = ['low', 'high']");
+        assert_eq!(check_with(&refinement, &level.new_val("high").unwrap()), Ok(()));
+        assert_eq!(
+            check_with(&refinement, &level.new_val("max").unwrap()),
+            Err("argument 'x' is 'max', not one of [\"low\", \"high\"]".to_string())
+        );
+    }
+
+    #[test]
+    fn flags_may_only_set_allowed_flags() {
+        let types = param_types();
+        let Type::Flags(access) = &types[3] else { panic!("unexpected parameter types {:?}", types) };
+
+        let refinement = rules("allowed = ['read', 'write']");
+        assert_eq!(check_with(&refinement, &access.new_val(&[]).unwrap()), Ok(()));
+        assert_eq!(check_with(&refinement, &access.new_val(&["read", "write"]).unwrap()), Ok(()));
+        assert_eq!(
+            check_with(&refinement, &access.new_val(&["read", "admin"]).unwrap()),
+            Err("argument 'x' is 'admin', not one of [\"read\", \"write\"]".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_fail_construction() {
+        let rules: BTreeMap<String, FunctionRules> =
+            toml::from_str("[find.args.query]\npattern = '('").unwrap();
+        assert!(ValidateMiddleware::new(rules).is_err());
+    }
+}
@@ -1,19 +1,22 @@
 use anyhow::{Context, Result, anyhow};
-use pypes_analyser::Workflow;
+use pypes_analyser::{split_function, Workflow};
 use std::collections::HashMap;
-use wasmtime::component::{Instance, Val};
+use wasmtime::component::{Func, Instance, ResourceAny, Val};
 use wasmtime::Store;
 use regex::Regex;
 use serde_json::Value;
 
-use crate::HostState;
+use crate::{HostState, RunningComponent};
 
 pub async fn execute(
-    mut store: &mut Store<HostState>,
-    instances: &HashMap<String, Instance>,
+    instances: &HashMap<String, RunningComponent>,
     workflow: &Workflow,
 ) -> Result<()> {
     let mut step_outputs: HashMap<String, Value> = HashMap::new();
+    // Resources returned by earlier steps, by step id, with the component that
+    // made them: the only one they can be passed back to. A step's output is then
+    // `{"resource": "<step id>"}`.
+    let mut handles: HashMap<String, (String, ResourceAny)> = HashMap::new();
     let re = Regex::new(r"\{\{\s*([a-zA-Z0-9_.]+)\s*\}\}").unwrap();
 
     println!("\n🚀 Starting Declarative Workflow Execution...\n");
@@ -22,30 +25,14 @@ pub async fn execute(
         println!("▶ Step '{}': Calling {}.{}", step.id, step.component, step.function);
 
         // 1. Get Instance
-        let instance = instances.get(&step.component)
+        let running = instances.get(&step.component)
             .ok_or_else(|| anyhow!("Component not found: {}", step.component))?;
+        let instance = running.instance;
+        let mut guard = running.store.lock().await;
+        let store = &mut *guard;
         
-        // 2. Get Function
-        // Note: This relies on the function being exported directly.
-        // If it's nested in an interface instance export, we need to handle that.
-        // e.g. "calendar-api#get-free-slots" vs "get-free-slots"
-        // For this POC, we check direct export first, then try some known interfaces?
-        // Or just assume simple exports for now as the user example suggested.
-        
-        let func = if step.function.contains('.') {
-            let parts: Vec<&str> = step.function.splitn(2, '.').collect();
-            let interface_name = parts[0];
-            let func_name = parts[1];
-            
-             let mut exports = instance.exports(&mut store);
-             if let Some(mut exported_instance) = exports.instance(interface_name) {
-                 exported_instance.func(func_name)
-             } else {
-                 None
-             }
-        } else {
-             instance.get_func(&mut store, &step.function)
-        }
+        // 2. Get Function (a root export, or "interface.function")
+        let func = export_func(&instance, store, &step.function)
             .ok_or_else(|| anyhow!("Function '{}' not found in component '{}' (checked root and all exports)", step.function, step.component))?;
 
         // 3. Prepare Arguments
@@ -57,7 +44,7 @@ pub async fn execute(
         // FOR POC: We assume functions take 0 args OR 1 String arg.
         // This covers get-free-slots() and predict-state(String).
         
-        let param_types = func.params(&store);
+        let param_types = func.params(&*store);
         
         // Match parameters
         // POC Simplification: We attempt to fill arguments by matching types
@@ -88,7 +75,24 @@ pub async fn execute(
             
             // If function takes 1 arg of type String, pass it.
             if param_types.len() == 1 {
-                 if matches!(param_types[0], wasmtime::component::Type::String) {
+                 if let wasmtime::component::Type::Own(_) | wasmtime::component::Type::Borrow(_) = param_types[0] {
+                      let from = serde_json::from_str::<Value>(&input_string).ok()
+                          .and_then(|json| json.get("resource").and_then(Value::as_str).map(str::to_string))
+                          .ok_or_else(|| anyhow!("Step '{}' expects a resource, but its input is not one: {}", step.id, input_string))?;
+                      let (owner, handle) = handles.get(&from).cloned()
+                          .ok_or_else(|| anyhow!("Step '{}' uses the resource from step '{}', which is gone", step.id, from))?;
+                      if owner != step.component {
+                          return Err(anyhow!(
+                              "Step '{}' passes the resource from step '{}' to '{}', but only '{}' can use it",
+                              step.id, from, step.component, owner
+                          ));
+                      }
+                      // Passing ownership hands the resource back for good.
+                      if matches!(param_types[0], wasmtime::component::Type::Own(_)) {
+                          handles.remove(&from);
+                      }
+                      args.push(Val::Resource(handle));
+                 } else if matches!(param_types[0], wasmtime::component::Type::String) {
                       args.push(Val::String(input_string.into()));
                  } else {
                       // Try to parse string as JSON to fit type?
@@ -107,33 +111,32 @@ pub async fn execute(
         } else {
              // No template. Check if we can map previous headers/outputs automatically?
              // Or just default to empty/none.
-             if param_types.is_empty() {
-                 // No args needed
-             } else {
-                 // If 1 arg, try to use previous step output if it matches? 
-                 // (Not implemented in POC, unsafe assumption).
-                 // Just push default/empty
-                 for ty in param_types.iter() {
-                     // Try to construct a "Default" val for type?
-                     // Or Error.
-                     return Err(anyhow!("Function expects arguments but no input mapping provided for step '{}'", step.id));
-                 }
+             // If 1 arg, try to use previous step output if it matches? 
+             // (Not implemented in POC, unsafe assumption).
+             if !param_types.is_empty() {
+                 return Err(anyhow!("Function expects arguments but no input mapping provided for step '{}'", step.id));
              }
         }
 
         // 4. Call Function
         // allocate space for results
-        let result_types = func.results(&store);
+        let result_types = func.results(&*store);
         let mut results = vec![Val::Bool(false); result_types.len()]; // Placeholder values
         
-        func.call_async(&mut store, &args, &mut results).await
+        func.call_async(&mut *store, &args, &mut results).await
             .context(format!("Failed to call {}.{}", step.component, step.function))?;
+        func.post_return_async(&mut *store).await?;
         
         // 5. Capture Output
-        if let Some(val) = results.first() {
+        if let Some(Val::Resource(handle)) = results.first() {
+            handles.insert(step.id.clone(), (step.component.clone(), *handle));
+            let json_val = serde_json::json!({ "resource": step.id });
+            println!("  ↩ Output: {}", json_val);
+            step_outputs.insert(step.id.clone(), json_val);
+        } else if let Some(val) = results.first() {
             // Get the type of the first result
             let ty = &result_types[0];
-            let json_val = val_to_json(val, ty, store);
+            let json_val = val_to_json(val, ty);
             println!("  ↩ Output: {}", json_val);
             step_outputs.insert(step.id.clone(), json_val);
         } else {
@@ -141,11 +144,32 @@ pub async fn execute(
         }
     }
     
+    for (component, handle) in handles.into_values() {
+        if handle.owned() {
+            let mut store = instances[&component].store.lock().await;
+            handle.resource_drop_async(&mut *store).await?;
+        }
+    }
+
     println!("\n✅ Workflow Complete.\n");
     Ok(())
 }
 
-fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostState>) -> Value {
+/// Looks up "function" among an instance's root exports, or "interface.function"
+/// among the functions of an exported interface (methods included, as in
+/// "interface.[method]cursor.next").
+pub fn export_func(instance: &Instance, store: &mut Store<HostState>, path: &str) -> Option<Func> {
+    match split_function(path) {
+        Some((interface_name, func_name)) => {
+            let mut exports = instance.exports(&mut *store);
+            let mut exported_instance = exports.instance(interface_name)?;
+            exported_instance.func(func_name)
+        }
+        None => instance.get_func(&mut *store, path),
+    }
+}
+
+pub fn val_to_json(val: &Val, ty: &wasmtime::component::Type) -> Value {
     match (val, ty) {
         (Val::Bool(b), _) => Value::Bool(*b),
         (Val::S8(i), _) => Value::Number((*i).into()),
@@ -162,7 +186,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
         (Val::String(s), _) => Value::String(s.to_string()),
         (Val::List(l), wasmtime::component::Type::List(list_ty)) => {
              let element_ty = list_ty.ty();
-             let values: Vec<Value> = l.iter().map(|v| val_to_json(v, &element_ty, store)).collect();
+             let values: Vec<Value> = l.iter().map(|v| val_to_json(v, &element_ty)).collect();
              Value::Array(values)
         },
         (Val::Record(rec), wasmtime::component::Type::Record(record_ty)) => {
@@ -171,7 +195,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
             for (name, val) in rec.fields() {
                 // Find the corresponding type
                 if let Some(field) = record_ty.fields().find(|f| f.name == name) {
-                    map.insert(name.to_string(), val_to_json(val, &field.ty, store));
+                    map.insert(name.to_string(), val_to_json(val, &field.ty));
                 }
             }
             Value::Object(map)
@@ -179,7 +203,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
         (Val::Tuple(tup), wasmtime::component::Type::Tuple(tuple_ty)) => {
              let types: Vec<_> = tuple_ty.types().collect();
              let json_values: Vec<Value> = tup.values().iter().zip(types.iter())
-                .map(|(v, t)| val_to_json(v, t, store))
+                .map(|(v, t)| val_to_json(v, t))
                 .collect();
              Value::Array(json_values)
         },
@@ -188,10 +212,8 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
              let mut map = serde_json::Map::new();
              if let Some(case) = variant_ty.cases().find(|c| c.name == discriminant_name) {
                  map.insert("tag".to_string(), Value::String(discriminant_name.to_string()));
-                 if let Some(payload) = v.payload() {
-                     if let Some(ty) = &case.ty {
-                         map.insert("val".to_string(), val_to_json(payload, ty, store));
-                     }
+                 if let (Some(payload), Some(ty)) = (v.payload(), &case.ty) {
+                     map.insert("val".to_string(), val_to_json(payload, ty));
                  }
                  Value::Object(map)
              } else {
@@ -201,7 +223,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
         (Val::Enum(e), _) => Value::String(e.discriminant().to_string()),
         (Val::Option(o), wasmtime::component::Type::Option(option_ty)) => {
              match o.value() {
-                Some(v) => val_to_json(v, &option_ty.ty(), store),
+                Some(v) => val_to_json(v, &option_ty.ty()),
                 None => Value::Null,
              }
         },
@@ -210,7 +232,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
                 Ok(opt) => {
                     let map = if let Some(v) = opt {
                          if let Some(ok_ty) = result_ty.ok() {
-                             let val = val_to_json(v, &ok_ty, store);
+                             let val = val_to_json(v, &ok_ty);
                              serde_json::Map::from_iter(vec![("ok".to_string(), val)])
                          } else {
                              serde_json::Map::from_iter(vec![("ok".to_string(), Value::Null)])
@@ -223,7 +245,7 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
                 Err(opt) => {
                     let map = if let Some(v) = opt {
                          if let Some(err_ty) = result_ty.err() {
-                             let val = val_to_json(v, &err_ty, store);
+                             let val = val_to_json(v, &err_ty);
                              serde_json::Map::from_iter(vec![("err".to_string(), val)])
                          } else {
                              serde_json::Map::from_iter(vec![("err".to_string(), Value::Null)])
@@ -236,26 +258,35 @@ fn val_to_json(val: &Val, ty: &wasmtime::component::Type, store: &Store<HostStat
             }
         },
         (Val::Flags(f), _) => Value::Array(f.flags().map(|s| Value::String(s.to_string())).collect()),
+        // Handles are opaque; see `execute` for how a workflow passes them on.
+        (Val::Resource(_), _) => Value::String("<resource>".to_string()),
         (v, t) => Value::String(format!("match_mismatch({:?}, {:?})", v, t)),
     }
 }
 
-fn json_to_val(json: &Value, ty: &wasmtime::component::Type) -> Result<Val> {
+pub fn json_to_val(json: &Value, ty: &wasmtime::component::Type) -> Result<Val> {
     use wasmtime::component::Type;
     match ty {
         Type::Bool => Ok(Val::Bool(json.as_bool().ok_or_else(|| anyhow!("Expected bool"))?)),
-        Type::S8 => Ok(Val::S8(json.as_i64().ok_or_else(|| anyhow!("Expected number"))? as i8)),
-        Type::U8 => Ok(Val::U8(json.as_u64().ok_or_else(|| anyhow!("Expected number"))? as u8)),
-        Type::S16 => Ok(Val::S16(json.as_i64().ok_or_else(|| anyhow!("Expected number"))? as i16)),
-        Type::U16 => Ok(Val::U16(json.as_u64().ok_or_else(|| anyhow!("Expected number"))? as u16)),
-        Type::S32 => Ok(Val::S32(json.as_i64().ok_or_else(|| anyhow!("Expected number"))? as i32)),
-        Type::U32 => Ok(Val::U32(json.as_u64().ok_or_else(|| anyhow!("Expected number"))? as u32)),
-        Type::S64 => Ok(Val::S64(json.as_i64().ok_or_else(|| anyhow!("Expected number"))?)),
-        Type::U64 => Ok(Val::U64(json.as_u64().ok_or_else(|| anyhow!("Expected number"))?)),
+        Type::S8 => Ok(Val::S8(signed(json)?)),
+        Type::U8 => Ok(Val::U8(unsigned(json)?)),
+        Type::S16 => Ok(Val::S16(signed(json)?)),
+        Type::U16 => Ok(Val::U16(unsigned(json)?)),
+        Type::S32 => Ok(Val::S32(signed(json)?)),
+        Type::U32 => Ok(Val::U32(unsigned(json)?)),
+        Type::S64 => Ok(Val::S64(signed(json)?)),
+        Type::U64 => Ok(Val::U64(unsigned(json)?)),
         Type::Float32 => Ok(Val::Float32(json.as_f64().ok_or_else(|| anyhow!("Expected number"))? as f32)),
         Type::Float64 => Ok(Val::Float64(json.as_f64().ok_or_else(|| anyhow!("Expected number"))?)),
-        Type::Char => Ok(Val::Char(json.as_str().ok_or_else(|| anyhow!("Expected char string"))?.chars().next().unwrap())),
-        Type::String => Ok(Val::String(json.as_str().unwrap_or(&json.to_string()).to_string().into())),
+        Type::Char => {
+            let s = json.as_str().ok_or_else(|| anyhow!("Expected char string"))?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Val::Char(c)),
+                _ => Err(anyhow!("Expected a single character, got {:?}", s)),
+            }
+        },
+        Type::String => Ok(Val::String(json.as_str().ok_or_else(|| anyhow!("Expected string, got {}", json))?.into())),
         Type::List(list_ty) => {
             let arr = json.as_array().ok_or_else(|| anyhow!("Expected array"))?;
             let elem_ty = list_ty.ty();
@@ -274,6 +305,9 @@ fn json_to_val(json: &Value, ty: &wasmtime::component::Type) -> Result<Val> {
         },
         Type::Tuple(tuple_ty) => {
              let arr = json.as_array().ok_or_else(|| anyhow!("Expected array for tuple"))?;
+             if arr.len() != tuple_ty.types().len() {
+                 return Err(anyhow!("Expected {} tuple elements, got {}", tuple_ty.types().len(), arr.len()));
+             }
              let mut values = Vec::new();
              for (json_v, ty) in arr.iter().zip(tuple_ty.types()) {
                  values.push(json_to_val(json_v, &ty)?);
@@ -304,6 +338,136 @@ fn json_to_val(json: &Value, ty: &wasmtime::component::Type) -> Result<Val> {
                 Ok(option_ty.new_val(Some(inner))?)
             }
         },
+        // The forms `val_to_json` produces: a case name, a list of flag names,
+        // and `{"ok": ...}` or `{"err": ...}`.
+        Type::Enum(enum_ty) => {
+            let name = json.as_str().ok_or_else(|| anyhow!("Expected enum case name"))?;
+            Ok(enum_ty.new_val(name)?)
+        },
+        Type::Flags(flags_ty) => {
+            let arr = json.as_array().ok_or_else(|| anyhow!("Expected array of flag names"))?;
+            let names = arr.iter()
+                .map(|v| v.as_str().ok_or_else(|| anyhow!("Expected flag name, got {}", v)))
+                .collect::<Result<Vec<&str>>>()?;
+            Ok(flags_ty.new_val(&names)?)
+        },
+        Type::Result(result_ty) => {
+            let obj = json.as_object().ok_or_else(|| anyhow!("Expected object for result"))?;
+            let payload = |key: &str, ty: Option<Type>| -> Result<Option<Val>> {
+                let v = &obj[key];
+                match ty {
+                    Some(ty) => Ok(Some(json_to_val(v, &ty)?)),
+                    None if v.is_null() => Ok(None),
+                    None => Err(anyhow!("'{}' of this result carries no value, got {}", key, v)),
+                }
+            };
+            let value = match (obj.contains_key("ok"), obj.contains_key("err"), obj.len()) {
+                (true, false, 1) => Ok(payload("ok", result_ty.ok())?),
+                (false, true, 1) => Err(payload("err", result_ty.err())?),
+                _ => return Err(anyhow!("Expected {{\"ok\": ...}} or {{\"err\": ...}} for result")),
+            };
+            Ok(result_ty.new_val(value)?)
+        },
         _ => Err(anyhow!("Unsupported type for json_to_val: {:?}", ty)),
     }
 }
+
+/// `json` as a signed integer that fits in `T`.
+fn signed<T: TryFrom<i64>>(json: &Value) -> Result<T> {
+    let n = json.as_i64().ok_or_else(|| anyhow!("Expected integer, got {}", json))?;
+    T::try_from(n).map_err(|_| anyhow!("{} is out of range", n))
+}
+
+/// `json` as an unsigned integer that fits in `T`.
+fn unsigned<T: TryFrom<u64>>(json: &Value) -> Result<T> {
+    let n = json.as_u64().ok_or_else(|| anyhow!("Expected non-negative integer, got {}", json))?;
+    T::try_from(n).map_err(|_| anyhow!("{} is out of range", n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::{Component, Linker, Type};
+    use wasmtime::{Engine, Store};
+
+    /// Parameter types of `f(small: u8, signed: s16, level: enum { low, high },
+    /// access: flags { read, write }, reply: result<u32, string>, text: string)`.
+    fn param_types() -> Vec<Type> {
+        let engine = Engine::default();
+        let component = Component::new(&engine, r#"(component
+            (core module $m
+                (memory (export "memory") 1)
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+                (func (export "f") (param i32 i32 i32 i32 i32 i32 i32 i32 i32)))
+            (core instance $i (instantiate $m))
+            (type $level' (enum "low" "high"))
+            (export $level "level" (type $level'))
+            (type $access' (flags "read" "write"))
+            (export $access "access" (type $access'))
+            (func $f (param "small" u8) (param "signed" s16) (param "level" $level) (param "access" $access)
+                (param "reply" (result u32 (error string))) (param "text" string)
+                (canon lift (core func $i "f") (memory $i "memory") (realloc (func $i "realloc"))))
+            (export "f" (func $f)))"#).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine).instantiate(&mut store, &component).unwrap();
+        instance.get_func(&mut store, "f").unwrap().params(&store).to_vec()
+    }
+
+    fn convert(json: &str, ty: &Type) -> Result<Val, String> {
+        json_to_val(&serde_json::from_str(json).unwrap(), ty).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn integers_must_fit_their_type() {
+        let types = param_types();
+        let (small, signed) = (&types[0], &types[1]);
+        assert_eq!(convert("255", small), Ok(Val::U8(255)));
+        assert_eq!(convert("256", small), Err("256 is out of range".to_string()));
+        assert_eq!(convert("-1", small), Err("Expected non-negative integer, got -1".to_string()));
+        assert_eq!(convert("-32768", signed), Ok(Val::S16(-32768)));
+        assert_eq!(convert("40000", signed), Err("40000 is out of range".to_string()));
+        assert_eq!(convert("1.5", signed), Err("Expected integer, got 1.5".to_string()));
+    }
+
+    #[test]
+    fn strings_are_not_made_from_other_values() {
+        let text = &param_types()[5];
+        assert_eq!(convert(r#""busy week""#, text), Ok(Val::String("busy week".into())));
+        assert_eq!(convert("5", text), Err("Expected string, got 5".to_string()));
+        assert_eq!(convert(r#"["busy"]"#, text), Err(r#"Expected string, got ["busy"]"#.to_string()));
+    }
+
+    #[test]
+    fn enums_flags_and_results_take_the_form_results_print_in() {
+        let types = param_types();
+        let (Type::Enum(level), Type::Flags(access), Type::Result(reply)) = (&types[2], &types[3], &types[4]) else {
+            panic!("unexpected parameter types {:?}", types);
+        };
+
+        assert_eq!(convert(r#""high""#, &types[2]), Ok(level.new_val("high").unwrap()));
+        assert!(convert(r#""max""#, &types[2]).is_err());
+        assert_eq!(convert("1", &types[2]), Err("Expected enum case name".to_string()));
+
+        assert_eq!(convert(r#"["read", "write"]"#, &types[3]), Ok(access.new_val(&["read", "write"]).unwrap()));
+        assert_eq!(convert("[]", &types[3]), Ok(access.new_val(&[]).unwrap()));
+        assert!(convert(r#"["root"]"#, &types[3]).is_err());
+        assert_eq!(convert(r#"[1]"#, &types[3]), Err("Expected flag name, got 1".to_string()));
+
+        assert_eq!(convert(r#"{"ok": 7}"#, &types[4]), Ok(reply.new_val(Ok(Some(Val::U32(7)))).unwrap()));
+        assert_eq!(
+            convert(r#"{"err": "busy"}"#, &types[4]),
+            Ok(reply.new_val(Err(Some(Val::String("busy".into())))).unwrap())
+        );
+        let neither = Err(r#"Expected {"ok": ...} or {"err": ...} for result"#.to_string());
+        assert_eq!(convert(r#"{"ok": 7, "err": "busy"}"#, &types[4]), neither);
+        assert_eq!(convert("{}", &types[4]), neither);
+        assert_eq!(convert(r#"{"ok": "7"}"#, &types[4]), Err(r#"Expected non-negative integer, got "7""#.to_string()));
+
+        // What `val_to_json` prints converts back to the same value.
+        for value in [level.new_val("low").unwrap(), access.new_val(&["write"]).unwrap()] {
+            assert_eq!(json_to_val(&val_to_json(&value, &value.ty()), &value.ty()).unwrap(), value);
+        }
+        let value = reply.new_val(Err(Some(Val::String("busy".into())))).unwrap();
+        assert_eq!(json_to_val(&val_to_json(&value, &types[4]), &types[4]).unwrap(), value);
+    }
+}
@@ -0,0 +1,171 @@
+use wit_parser::{Function, Resolve, Type, TypeDefKind};
+
+use crate::catalogue::{interface_of, is_root_export};
+use crate::dataflow::template_refs;
+use crate::{Blueprint, Environment, SafetyViolation, ViolationType};
+
+/// One channel a blueprint opens between two components: a wire, or a workflow
+/// edge where one step's output is templated into the next step's input.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    /// The receiving component.
+    pub component: String,
+    /// e.g. "wire 'leaky_agent.llm' -> 'llm_provider.local:calendar-privacy/llm-api'"
+    pub description: String,
+    /// Most information one call can return, in bits. `f64::INFINITY` for strings,
+    /// lists and handles; `None` when no WIT describes the provider.
+    pub bits: Option<f64>,
+    pub max_bits: Option<u32>,
+}
+
+impl Channel {
+    pub fn exceeds_budget(&self) -> bool {
+        match (self.bits, self.max_bits) {
+            (Some(bits), Some(max_bits)) => bits > max_bits as f64,
+            // An unknown capacity cannot be shown to fit a budget.
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+}
+
+/// "64.0 bits", "2.3 bits" or "unbounded".
+pub fn format_bits(bits: f64) -> String {
+    if bits.is_infinite() {
+        "unbounded".to_string()
+    } else {
+        format!("{:.1} bits", bits)
+    }
+}
+
+/// Capacity of every wire between components and every workflow edge, sized from
+/// the providers' WIT; host wires have no WIT and report `None`. Channels out of
+/// a pending component are left out until it can be sized.
+pub fn channels(blueprint: &Blueprint, env: &Environment) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    let wit = &env.wit;
+
+    let mut wires: Vec<_> = blueprint.wiring.iter().collect();
+    wires.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    for (consumer_key, connection) in wires {
+        let consumer = consumer_key.split('.').next().unwrap_or(consumer_key);
+        let provider_key = connection.provider();
+        let provider = provider_key.split('.').next().unwrap_or(provider_key);
+        let max_bits = connection.max_bits();
+        if (provider == "host" && max_bits.is_none()) || env.pending.contains(provider) {
+            continue;
+        }
+
+        let interface = interface_of(provider_key);
+        let bits = wit.get(provider).and_then(|loader| {
+            if is_root_export(interface) {
+                return loader.get_function(interface).map(|func| result_bits(&loader.resolve, func));
+            }
+            let functions = match connection.functions() {
+                Some(functions) => functions.to_vec(),
+                None => loader.get_interface_exports(interface).ok()?,
+            };
+            // A call returns the result of one function, so the wire carries as much
+            // as its widest granted function.
+            functions.iter().try_fold(0.0_f64, |widest, function| {
+                let func = loader.get_function(&format!("{}.{}", interface, function))?;
+                Some(widest.max(result_bits(&loader.resolve, func)))
+            })
+        });
+
+        channels.push(Channel {
+            component: consumer.to_string(),
+            description: format!("wire '{}' -> '{}'", consumer_key, provider_key),
+            bits,
+            max_bits,
+        });
+    }
+
+    if let Some(workflow) = &blueprint.workflow {
+        for (i, step) in workflow.steps.iter().enumerate() {
+            let Some(input) = &step.input else { continue };
+            for step_id in template_refs(input) {
+                // Steps only see outputs of steps that ran before them.
+                let Some(producer) = workflow.steps[..i].iter().find(|s| s.id == step_id) else { continue };
+                if env.pending.contains(&producer.component) {
+                    continue;
+                }
+                let bits = wit.get(&producer.component).and_then(|loader| {
+                    loader.get_function(&producer.function).map(|func| result_bits(&loader.resolve, func))
+                });
+                channels.push(Channel {
+                    component: step.component.clone(),
+                    description: format!("workflow edge '{}' -> '{}'", producer.id, step.id),
+                    bits,
+                    max_bits: step.max_bits,
+                });
+            }
+        }
+    }
+
+    channels
+}
+
+/// Reports every channel whose capacity exceeds (or cannot be shown to fit) its
+/// `max_bits` budget.
+pub(crate) fn check_channels(channels: &[Channel]) -> Vec<SafetyViolation> {
+    channels.iter().filter(|c| c.exceeds_budget()).map(|channel| {
+        let max_bits = channel.max_bits.unwrap_or_default();
+        let details = match channel.bits {
+            Some(bits) => format!(
+                "{} can carry {} per call, over its budget of {} bits.",
+                channel.description, format_bits(bits), max_bits
+            ),
+            None => format!(
+                "{} has a budget of {} bits, but no WIT describes what it returns.",
+                channel.description, max_bits
+            ),
+        };
+        SafetyViolation::new(&channel.component, ViolationType::BandwidthExceeded, details)
+    }).collect()
+}
+
+/// Bits needed to encode any result of `func`.
+pub fn result_bits(resolve: &Resolve, func: &Function) -> f64 {
+    func.results.iter_types().map(|ty| type_bits(resolve, ty)).sum()
+}
+
+/// Bits needed to encode any value of `ty`: log2 of the number of distinct values.
+pub fn type_bits(resolve: &Resolve, ty: &Type) -> f64 {
+    match ty {
+        Type::Bool => 1.0,
+        Type::U8 | Type::S8 => 8.0,
+        Type::U16 | Type::S16 => 16.0,
+        Type::U32 | Type::S32 | Type::Float32 => 32.0,
+        Type::U64 | Type::S64 | Type::Float64 => 64.0,
+        // Unicode scalar values, i.e. code points minus surrogates.
+        Type::Char => ((0x11_0000 - 0x800) as f64).log2(),
+        Type::String => f64::INFINITY,
+        Type::Id(id) => match &resolve.types[*id].kind {
+            TypeDefKind::Type(ty) => type_bits(resolve, ty),
+            TypeDefKind::Record(record) => record.fields.iter().map(|f| type_bits(resolve, &f.ty)).sum(),
+            TypeDefKind::Tuple(tuple) => tuple.types.iter().map(|ty| type_bits(resolve, ty)).sum(),
+            TypeDefKind::Flags(flags) => flags.flags.len() as f64,
+            TypeDefKind::Enum(e) => alternatives(e.cases.iter().map(|_| 0.0)),
+            TypeDefKind::Variant(variant) => alternatives(
+                variant.cases.iter().map(|case| case.ty.as_ref().map_or(0.0, |ty| type_bits(resolve, ty))),
+            ),
+            TypeDefKind::Option(ty) => alternatives([0.0, type_bits(resolve, ty)]),
+            TypeDefKind::Result(result) => alternatives(
+                [&result.ok, &result.err].map(|ty| ty.as_ref().map_or(0.0, |ty| type_bits(resolve, ty))),
+            ),
+            // Unbounded, or (for handles) a reference to state the receiver can query.
+            TypeDefKind::List(_)
+            | TypeDefKind::Resource
+            | TypeDefKind::Handle(_)
+            | TypeDefKind::Future(_)
+            | TypeDefKind::Stream(_)
+            | TypeDefKind::Unknown => f64::INFINITY,
+        },
+    }
+}
+
+/// Capacity of a choice between cases carrying `case_bits` each: log2(Σ 2^bits).
+fn alternatives(case_bits: impl IntoIterator<Item = f64>) -> f64 {
+    case_bits.into_iter().map(f64::exp2).sum::<f64>().log2().max(0.0)
+}
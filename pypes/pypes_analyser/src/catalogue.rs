@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{Blueprint, Capability, Environment};
+
+/// Catalogue format understood by this version of the analyser.
+pub const CATALOGUE_VERSION: u32 = 1;
+
+const BUILTIN: &str = include_str!("../capabilities.toml");
+
+/// Maps fully qualified WIT interfaces (and their functions) to capabilities.
+///
+/// ```toml
+/// version = 1
+///
+/// [interfaces."wasi:http/outgoing-handler"]
+/// capabilities = ["exfiltration", "untrusted-input"]
+///
+/// [interfaces."local:calendar-privacy/calendar-api".functions]
+/// get-free-slots = []
+/// get-events-sensitive = ["internal-data"]
+///
+/// # A root function, as "<package>#<function>"
+/// [interfaces."local:greeter#greet"]
+/// capabilities = []
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Catalogue {
+    pub version: u32,
+    /// Fall back to interface-name heuristics for interfaces missing from the
+    /// catalogue instead of reporting them as unclassified.
+    #[serde(default)]
+    pub heuristic_fallback: bool,
+    #[serde(default)]
+    pub interfaces: BTreeMap<String, InterfaceEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct InterfaceEntry {
+    /// Capabilities of the interface as a whole, whichever function is called.
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub functions: BTreeMap<String, Vec<Capability>>,
+}
+
+impl Catalogue {
+    /// The catalogue shipped with pypes (WASI plus the bundled examples).
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("built-in capability catalogue is valid")
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let catalogue: Catalogue = toml::from_str(content).map_err(|e| e.to_string())?;
+        if catalogue.version != CATALOGUE_VERSION {
+            return Err(format!(
+                "Unsupported catalogue version {} (expected {})",
+                catalogue.version, CATALOGUE_VERSION
+            ));
+        }
+        Ok(catalogue)
+    }
+
+    /// Looks up `interface`, ignoring a trailing `@version` if there is no exact entry.
+    pub fn entry(&self, interface: &str) -> Option<&InterfaceEntry> {
+        self.interfaces.get(interface).or_else(|| {
+            let unversioned = interface.split('@').next().unwrap_or(interface);
+            self.interfaces.get(unversioned)
+        })
+    }
+
+    /// Capabilities granted by wiring `functions` of `interface` (every function when
+    /// `None`). Errors name what is missing from the catalogue when heuristics are off.
+    ///
+    /// An interface with a `functions` table must list every function granted over a
+    /// wire; interface-level `capabilities` apply whichever functions are granted.
+    pub fn capabilities(&self, interface: &str, functions: Option<&[String]>) -> Result<HashSet<Capability>, String> {
+        let Some(entry) = self.entry(interface) else {
+            if self.heuristic_fallback {
+                return Ok(infer_capabilities(interface).into_iter().collect());
+            }
+            let kind = if interface.contains('#') { "Root function" } else { "Interface" };
+            return Err(format!("{} '{}'", kind, interface));
+        };
+
+        let mut caps: HashSet<Capability> = entry.capabilities.iter().copied().collect();
+        match functions {
+            None => {
+                for fn_caps in entry.functions.values() {
+                    caps.extend(fn_caps.iter().copied());
+                }
+            }
+            Some(functions) => {
+                for function in functions {
+                    match entry.functions.get(function) {
+                        Some(fn_caps) => caps.extend(fn_caps.iter().copied()),
+                        None if entry.functions.is_empty() => {}
+                        None => return Err(format!("Function '{}' of interface '{}'", function, interface)),
+                    }
+                }
+            }
+        }
+        Ok(caps)
+    }
+
+    /// Whether calling `functions` of `interface` hands the caller none of the
+    /// provider's data: the catalogue lists each of them as `[]`. The provider's
+    /// authority still reaches the caller, as it acts on the caller's behalf. Only an
+    /// explicit grant qualifies, as the catalogue need not list every function of an
+    /// interface.
+    /// A root function's entry is the function itself, so `capabilities = []` is one.
+    pub fn passes_no_data(&self, interface: &str, functions: Option<&[String]>) -> bool {
+        if interface.contains('#') {
+            return self.entry(interface).is_some_and(|e| e.capabilities.is_empty() && e.functions.is_empty());
+        }
+        let (Some(entry), Some(functions)) = (self.entry(interface), functions) else {
+            return false;
+        };
+        entry.capabilities.is_empty()
+            && !functions.is_empty()
+            && functions.iter().all(|f| entry.functions.get(f).is_some_and(Vec::is_empty))
+    }
+
+    /// Wired interfaces and root functions the catalogue does not know about. With
+    /// `heuristic_fallback` these are classified by name, which callers should warn
+    /// about. Root functions whose package is unknown are left to `verify`.
+    pub fn unknown_interfaces(&self, blueprint: &Blueprint, env: &Environment) -> Vec<String> {
+        let mut unknown: Vec<String> = blueprint.wiring.values()
+            .filter_map(|c| env.catalogue_key(c.provider()))
+            .filter(|i| self.entry(i).is_none())
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        unknown
+    }
+}
+
+/// "provider.wasi:http/outgoing-handler" -> "wasi:http/outgoing-handler"
+pub fn interface_of(provider_key: &str) -> &str {
+    provider_key.split_once('.').map_or(provider_key, |(_, interface)| interface)
+}
+
+/// Whether a wire's export is a root function ("greeter.greet") rather than an
+/// interface ("calendar.local:calendar/read"): interfaces are always wired by
+/// their package-qualified name.
+pub fn is_root_export(export: &str) -> bool {
+    !export.contains('/')
+}
+
+/// "interface.function" -> ("interface", "function"), where the function may be a
+/// method, as in "interface.[method]cursor.next". `None` for a bare root function.
+/// Workflow steps name their function this way.
+pub fn split_function(path: &str) -> Option<(&str, &str)> {
+    match path.find(".[") {
+        Some(pos) => Some((&path[..pos], &path[pos + 1..])),
+        None => path.rsplit_once('.'),
+    }
+}
+
+/// "local:greeter" and "greet" -> "local:greeter#greet", the catalogue entry of a
+/// root function.
+pub fn root_key(package: &str, function: &str) -> String {
+    format!("{}#{}", package, function)
+}
+
+fn infer_capabilities(interface: &str) -> Vec<Capability> {
+    let mut caps = Vec::new();
+
+    // Heuristics based on interface names. Only used when the catalogue opts in.
+
+    // Exfiltration / Untrusted Source
+    if interface.contains("http") || interface.contains("search") || interface.contains("network") {
+        caps.push(Capability::Exfiltration);
+        caps.push(Capability::UntrustedInput); // Responses are untrusted
+    }
+
+    // Internal Knowledge
+    if (interface.contains("calendar") || interface.contains("filesystem") || interface.contains("read")) && !interface.contains("propose") {
+        caps.push(Capability::InternalData);
+    }
+
+    // Destructive
+    // IMPORTANT: 'propose' is NOT destructive because it requires human approval
+    // (`verify` checks the wire actually carries the `approval` middleware).
+    if (interface.contains("delete") || interface.contains("write") || interface.contains("modify")) && !interface.contains("propose") {
+        caps.push(Capability::Destructive);
+    }
+
+    // Proposal (Safe)
+    if interface.contains("propose") {
+        caps.push(Capability::Proposal);
+    }
+
+    // Special case: LLM inference (usually compute, but if wired to others...)
+    // Treat LLM as benign by default, it just processes data.
+
+    caps
+}
@@ -0,0 +1,278 @@
+use crate::catalogue::{interface_of, is_root_export};
+use crate::component_types::{FuncType, Item};
+use crate::{Blueprint, Environment, SafetyViolation, ViolationType};
+
+/// Checks every wire between components against both component binaries: the
+/// consumer imports what the wire names, and every function it imports there is
+/// granted on the wire and exported by the provider with the same shape. Types
+/// are compared structurally: field, case and flag names count, type and
+/// parameter names do not. A wire that cannot be checked because a binary could
+/// not be read fails; pending components are checked by the run that fetches them.
+/// A `functions` grant leaving out an import is a misconfigured wire: the consumer
+/// cannot be instantiated, whatever is allowed. A function returning a handle inside
+/// a composite fails too, as the runtime can only hand over handles returned as
+/// results themselves; on a diode wire, which refuses every handle, it is left be.
+pub(crate) fn check_wires(blueprint: &Blueprint, env: &Environment) -> Vec<SafetyViolation> {
+    let mut violations = Vec::new();
+
+    let mut wires: Vec<_> = blueprint.wiring.iter().collect();
+    wires.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    for (consumer_key, connection) in wires {
+        let Some((consumer, import)) = consumer_key.split_once('.') else { continue };
+        let provider_key = connection.provider();
+        let provider = provider_key.split('.').next().unwrap_or(provider_key);
+        let export = interface_of(provider_key);
+        if provider == "host" || env.pending.contains(consumer) || env.pending.contains(provider) {
+            continue;
+        }
+        let wire = format!("wire '{}' -> '{}'", consumer_key, provider_key);
+        let mut ungranted = Vec::new();
+        let mut fail = |details: String| {
+            violations.push(SafetyViolation::new(consumer, ViolationType::TypeMismatch, format!("{}: {}", wire, details)));
+        };
+
+        let (Some(consumer_types), Some(provider_types)) = (env.types.get(consumer), env.types.get(provider)) else {
+            let unread = if env.types.contains_key(consumer) { provider } else { consumer };
+            fail(format!("cannot be type-checked without the component binary of '{}'.", unread));
+            continue;
+        };
+        let Some(wanted) = consumer_types.import(import) else {
+            fail(format!("'{}' does not import '{}'.", consumer, import));
+            continue;
+        };
+        let Some(offered) = provider_types.export(export) else {
+            fail(format!("'{}' does not export '{}'.", provider, export));
+            continue;
+        };
+
+        // Only a root function is wired by a bare name; it is classified as one.
+        if is_root_export(export) && matches!(offered, Item::Instance { .. }) {
+            fail(format!(
+                "'{}' of '{}' is an interface; wire it by its package-qualified name.",
+                export, provider
+            ));
+            continue;
+        }
+        match (wanted, offered) {
+            (Item::Func(wanted), Item::Func(offered)) => {
+                if let Some(difference) = signature_difference(wanted, offered) {
+                    fail(format!(
+                        "'{}' in '{}' is {}, but '{}' exports {} ({}).",
+                        import, consumer, wanted, provider, offered, difference
+                    ));
+                } else if offered.returns_nested_handles() && !connection.is_diode() {
+                    fail(nested_handles(import, offered));
+                }
+            }
+            (Item::Instance { functions: wanted, .. }, Item::Instance { functions: offered, .. }) => {
+                for (function, wanted) in wanted {
+                    if !connection.is_function_granted(function) {
+                        ungranted.push(function);
+                        continue;
+                    }
+                    let Some(offered) = offered.get(function) else {
+                        fail(format!("'{}' imports '{}', which '{}' does not export.", consumer, function, provider));
+                        continue;
+                    };
+                    if let Some(difference) = signature_difference(wanted, offered) {
+                        fail(format!(
+                            "'{}' in '{}' is {}, but '{}' exports {} ({}).",
+                            function, consumer, wanted, provider, offered, difference
+                        ));
+                    } else if offered.returns_nested_handles() && !connection.is_diode() {
+                        fail(nested_handles(function, offered));
+                    }
+                }
+            }
+            (Item::Func(_), Item::Instance { .. }) => {
+                fail(format!("'{}' imports a function, but '{}' exports an interface.", consumer, provider));
+            }
+            (Item::Instance { .. }, Item::Func(_)) => {
+                fail(format!("'{}' imports an interface, but '{}' exports a function.", consumer, provider));
+            }
+        }
+        for function in ungranted {
+            violations.push(SafetyViolation::new(
+                consumer,
+                ViolationType::MisconfiguredWire,
+                format!("{}: '{}' imports '{}', which the wire does not grant; it cannot be instantiated.", wire, consumer, function),
+            ));
+        }
+    }
+
+    violations
+}
+
+fn nested_handles(function: &str, func: &FuncType) -> String {
+    format!(
+        "'{}' is {}, which returns a resource inside another type; only resources returned directly can cross a wire.",
+        function, func
+    )
+}
+
+/// The first way the function a consumer imports differs from the one the
+/// provider exports, if it does.
+pub(crate) fn signature_difference(wanted: &FuncType, offered: &FuncType) -> Option<String> {
+    if wanted.params.len() != offered.params.len() {
+        return Some(format!("{} parameters against {}", wanted.params.len(), offered.params.len()));
+    }
+    for (i, ((name, wanted), (_, offered))) in wanted.params.iter().zip(&offered.params).enumerate() {
+        if wanted != offered {
+            return Some(format!("parameter {} ('{}') differs", i + 1, name));
+        }
+    }
+    if wanted.results != offered.results {
+        return Some("results differ".to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component_types::{ComponentTypes, ValueType};
+    use std::collections::BTreeMap;
+
+    fn func(params: &[(&str, ValueType)], results: &[ValueType]) -> FuncType {
+        FuncType {
+            params: params.iter().map(|(name, ty)| (name.to_string(), ty.clone())).collect(),
+            results: results.to_vec(),
+        }
+    }
+
+    fn instance(functions: &[(&str, FuncType)]) -> Item {
+        Item::Instance {
+            functions: functions.iter().map(|(name, f)| (name.to_string(), f.clone())).collect(),
+            resources: Vec::new(),
+        }
+    }
+
+    fn component(imports: &[(&str, Item)], exports: &[(&str, Item)]) -> ComponentTypes {
+        let map = |items: &[(&str, Item)]| -> BTreeMap<String, Item> {
+            items.iter().map(|(name, item)| (name.to_string(), item.clone())).collect()
+        };
+        ComponentTypes { imports: map(imports), exports: map(exports) }
+    }
+
+    fn violations(wiring: &str, agent: ComponentTypes, provider: ComponentTypes) -> Vec<SafetyViolation> {
+        let blueprint: Blueprint = toml::from_str(&format!(
+            "[components]\nagent = \"agent.wasm\"\nprovider = \"provider.wasm\"\n[wiring]\n{}",
+            wiring
+        )).unwrap();
+        let env = Environment {
+            types: [("agent".to_string(), agent), ("provider".to_string(), provider)].into(),
+            ..Default::default()
+        };
+        check_wires(&blueprint, &env)
+    }
+
+    fn check(wiring: &str, agent: ComponentTypes, provider: ComponentTypes) -> Vec<String> {
+        violations(wiring, agent, provider).into_iter().map(|v| v.details).collect()
+    }
+
+    #[test]
+    fn signatures_compare_types_not_parameter_names() {
+        let wanted = func(&[("text", ValueType::String)], &[ValueType::U32]);
+        assert_eq!(signature_difference(&wanted, &func(&[("body", ValueType::String)], &[ValueType::U32])), None);
+        assert_eq!(
+            signature_difference(&wanted, &func(&[("text", ValueType::String), ("n", ValueType::U8)], &[ValueType::U32])),
+            Some("1 parameters against 2".to_string())
+        );
+        assert_eq!(
+            signature_difference(&wanted, &func(&[("text", ValueType::U8)], &[ValueType::U32])),
+            Some("parameter 1 ('text') differs".to_string())
+        );
+        assert_eq!(
+            signature_difference(&wanted, &func(&[("text", ValueType::String)], &[])),
+            Some("results differ".to_string())
+        );
+    }
+
+    #[test]
+    fn signatures_compare_field_and_case_names() {
+        let record = |field: &str| func(&[], &[ValueType::Record(vec![(field.to_string(), ValueType::Bool)])]);
+        assert!(signature_difference(&record("busy"), &record("busy")).is_none());
+        assert!(signature_difference(&record("busy"), &record("free")).is_some());
+        let level = |cases: &[&str]| func(&[], &[ValueType::Enum(cases.iter().map(|c| c.to_string()).collect())]);
+        assert!(signature_difference(&level(&["low", "high"]), &level(&["low", "high", "max"])).is_some());
+    }
+
+    #[test]
+    fn matching_wires_pass() {
+        let read = func(&[("day", ValueType::U32)], &[ValueType::String]);
+        let agent = component(&[("local:cal/read", instance(&[("read", read.clone())]))], &[]);
+        let provider = component(&[], &[("local:cal/read", instance(&[("read", read), ("write", func(&[], &[]))]))]);
+        assert!(check("\"agent.local:cal/read\" = \"provider.local:cal/read\"", agent, provider).is_empty());
+    }
+
+    #[test]
+    fn mismatched_and_missing_functions_fail() {
+        let agent = component(&[("local:cal/read", instance(&[
+            ("read", func(&[("day", ValueType::U32)], &[ValueType::String])),
+            ("list", func(&[], &[])),
+        ]))], &[]);
+        let provider = component(&[], &[("local:cal/read", instance(&[
+            ("read", func(&[("day", ValueType::U64)], &[ValueType::String])),
+        ]))]);
+        let details = check("\"agent.local:cal/read\" = \"provider.local:cal/read\"", agent, provider);
+        assert_eq!(details.len(), 2);
+        assert!(details[0].contains("'list', which 'provider' does not export"));
+        assert!(details[1].contains("parameter 1 ('day') differs"));
+    }
+
+    #[test]
+    fn ungranted_imports_are_misconfigured() {
+        let agent = component(&[("local:cal/read", instance(&[("read", func(&[], &[])), ("delete", func(&[], &[]))]))], &[]);
+        let provider = component(&[], &[("local:cal/read", instance(&[("read", func(&[], &[])), ("delete", func(&[], &[]))]))]);
+        let violations = violations(
+            "\"agent.local:cal/read\" = { provider = \"provider.local:cal/read\", functions = [\"read\"] }",
+            agent, provider,
+        );
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].violation, ViolationType::MisconfiguredWire);
+        assert!(violations[0].details.contains("'delete', which the wire does not grant"));
+    }
+
+    #[test]
+    fn root_wires_need_root_functions() {
+        let ask = func(&[("n", ValueType::U32)], &[ValueType::U32]);
+        let agent = || component(&[("ask", Item::Func(ask.clone()))], &[]);
+        let ok = check("\"agent.ask\" = \"provider.greet\"", agent(), component(&[], &[("greet", Item::Func(ask.clone()))]));
+        assert!(ok.is_empty());
+        let bare_interface = check(
+            "\"agent.ask\" = \"provider.greet\"",
+            agent(),
+            component(&[], &[("greet", instance(&[("greet", ask.clone())]))]),
+        );
+        assert!(bare_interface[0].contains("wire it by its package-qualified name"));
+    }
+
+    #[test]
+    fn unread_binaries_fail_and_pending_ones_wait() {
+        let blueprint: Blueprint = toml::from_str(
+            "[components]\nagent = \"agent.wasm\"\nprovider = \"remote://provider\"\n[wiring]\n\"agent.ask\" = \"provider.greet\"",
+        ).unwrap();
+        let mut env = Environment::default();
+        env.types.insert("agent".to_string(), component(&[("ask", Item::Func(func(&[], &[])))], &[]));
+        let details: Vec<String> = check_wires(&blueprint, &env).into_iter().map(|v| v.details).collect();
+        assert!(details[0].contains("without the component binary of 'provider'"));
+        env.pending.insert("provider".to_string());
+        assert!(check_wires(&blueprint, &env).is_empty());
+    }
+
+    #[test]
+    fn nested_handle_results_fail_unless_the_wire_is_a_diode() {
+        let cursor = || ValueType::Own("cursor".to_string());
+        let open = func(&[], &[cursor()]);
+        let find = func(&[], &[ValueType::Option(Box::new(cursor()))]);
+        let close = func(&[("list", ValueType::List(Box::new(cursor())))], &[]);
+        let agent = || component(&[("local:db/rows", instance(&[("open", open.clone()), ("find", find.clone()), ("close", close.clone())]))], &[]);
+        let provider = || component(&[], &[("local:db/rows", instance(&[("open", open.clone()), ("find", find.clone()), ("close", close.clone())]))]);
+
+        let details = check("\"agent.local:db/rows\" = \"provider.local:db/rows\"", agent(), provider());
+        assert_eq!(details.len(), 1);
+        assert!(details[0].contains("'find' is func() -> option<cursor>, which returns a resource inside another type"));
+        let diode = "\"agent.local:db/rows\" = { provider = \"provider.local:db/rows\", diode = true }";
+        assert!(check(diode, agent(), provider()).is_empty());
+    }
+}
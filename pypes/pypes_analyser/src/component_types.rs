@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use wasmparser::types::{
+    ComponentAnyTypeId, ComponentDefinedType, ComponentEntityType, ComponentFuncType, ComponentValType, ResourceId,
+    Types,
+};
+use wasmparser::{Parser, Payload, PrimitiveValType, Validator};
+
+/// The imports and exports of a component binary with their types, read from the
+/// binary itself, so components shipped without WIT are described too.
+#[derive(Debug, Clone, Default)]
+pub struct ComponentTypes {
+    pub imports: BTreeMap<String, Item>,
+    pub exports: BTreeMap<String, Item>,
+}
+
+/// A function, or an instance such as "local:calendar-privacy/search-api".
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Func(FuncType),
+    Instance {
+        functions: BTreeMap<String, FuncType>,
+        /// Names of the resource types it carries, e.g. "cursor".
+        resources: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncType {
+    pub params: Vec<(String, ValueType)>,
+    pub results: Vec<ValueType>,
+}
+
+/// A value type as the binary declares it. Type names are not part of it; field,
+/// case and flag names are. Resources are known by name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    Bool,
+    S8,
+    U8,
+    S16,
+    U16,
+    S32,
+    U32,
+    S64,
+    U64,
+    Float32,
+    Float64,
+    Char,
+    String,
+    Record(Vec<(String, ValueType)>),
+    Variant(Vec<(String, Option<ValueType>)>),
+    List(Box<ValueType>),
+    Tuple(Vec<ValueType>),
+    Flags(Vec<String>),
+    Enum(Vec<String>),
+    Option(Box<ValueType>),
+    Result { ok: Option<Box<ValueType>>, err: Option<Box<ValueType>> },
+    Own(String),
+    Borrow(String),
+}
+
+impl ComponentTypes {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read component {:?}", path))?;
+        Self::from_binary(&bytes).with_context(|| format!("Failed to read the types of component {:?}", path))
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let (import_names, export_names) = top_level_names(bytes)?;
+        let types = Validator::new().validate_all(bytes)?;
+
+        let imported: Vec<_> = import_names.iter()
+            .filter_map(|name| Some((name, types.component_entity_type_of_import(name)?)))
+            .collect();
+        let exported: Vec<_> = export_names.iter()
+            .filter_map(|name| Some((name, types.component_entity_type_of_export(name)?)))
+            .collect();
+
+        // Every resource type declared anywhere at the top level, by id, so
+        // handles can be named wherever they turn up.
+        let mut resources = HashMap::new();
+        for (name, entity) in imported.iter().chain(&exported) {
+            name_resources(&types, name, entity, &mut resources);
+        }
+
+        let reader = Reader { types: &types, resources: &resources };
+        Ok(Self {
+            imports: imported.into_iter().filter_map(|(name, entity)| Some((name.clone(), reader.item(&entity)?))).collect(),
+            exports: exported.into_iter().filter_map(|(name, entity)| Some((name.clone(), reader.item(&entity)?))).collect(),
+        })
+    }
+
+    /// Imports something has to provide: bare functions, and instances with
+    /// functions in them. Instances imported only for their types (a `use` in
+    /// WIT) need no provider.
+    pub fn linked_imports(&self) -> Vec<String> {
+        self.imports.iter()
+            .filter(|(_, item)| match item {
+                Item::Func(_) => true,
+                Item::Instance { functions, .. } => !functions.is_empty(),
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// The import a wire names, which may leave out the version
+    /// ("wasi:cli/stdin" for "wasi:cli/stdin@0.2.0").
+    pub fn import(&self, name: &str) -> Option<&Item> {
+        find(&self.imports, name)
+    }
+
+    pub fn export(&self, name: &str) -> Option<&Item> {
+        find(&self.exports, name)
+    }
+}
+
+fn find<'a>(items: &'a BTreeMap<String, Item>, name: &str) -> Option<&'a Item> {
+    items.get(name).or_else(|| {
+        items.iter().find(|(key, _)| key.split('@').next() == Some(name)).map(|(_, item)| item)
+    })
+}
+
+/// Names imported and exported by the component itself, not by components and
+/// modules nested in it.
+fn top_level_names(bytes: &[u8]) -> Result<(Vec<String>, Vec<String>)> {
+    let (mut imports, mut exports) = (Vec::new(), Vec::new());
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload? {
+            Payload::Version { .. } => depth += 1,
+            Payload::End(_) => depth -= 1,
+            _ if depth != 1 => {}
+            Payload::ComponentImportSection(reader) => {
+                for import in reader {
+                    imports.push(import?.name.0.to_string());
+                }
+            }
+            Payload::ComponentExportSection(reader) => {
+                for export in reader {
+                    exports.push(export?.name.0.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((imports, exports))
+}
+
+fn name_resources(types: &Types, name: &str, entity: &ComponentEntityType, names: &mut HashMap<ResourceId, String>) {
+    match entity {
+        ComponentEntityType::Type { referenced, created } => {
+            for id in [referenced, created] {
+                if let ComponentAnyTypeId::Resource(resource) = id {
+                    names.entry(resource.resource()).or_insert_with(|| name.to_string());
+                }
+            }
+        }
+        ComponentEntityType::Instance(id) => {
+            for (name, entity) in &types[*id].exports {
+                name_resources(types, name, entity, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Reader<'a> {
+    types: &'a Types,
+    resources: &'a HashMap<ResourceId, String>,
+}
+
+impl Reader<'_> {
+    fn item(&self, entity: &ComponentEntityType) -> Option<Item> {
+        match entity {
+            ComponentEntityType::Func(id) => Some(Item::Func(self.func(&self.types[*id]))),
+            ComponentEntityType::Instance(id) => {
+                let mut functions = BTreeMap::new();
+                let mut resources = Vec::new();
+                for (name, entity) in &self.types[*id].exports {
+                    match entity {
+                        ComponentEntityType::Func(id) => {
+                            functions.insert(name.clone(), self.func(&self.types[*id]));
+                        }
+                        ComponentEntityType::Type { created: ComponentAnyTypeId::Resource(_), .. } => {
+                            resources.push(name.clone());
+                        }
+                        _ => {}
+                    }
+                }
+                Some(Item::Instance { functions, resources })
+            }
+            _ => None,
+        }
+    }
+
+    fn func(&self, func: &ComponentFuncType) -> FuncType {
+        FuncType {
+            params: func.params.iter().map(|(name, ty)| (name.to_string(), self.value(ty))).collect(),
+            results: func.results.iter().map(|(_, ty)| self.value(ty)).collect(),
+        }
+    }
+
+    fn value(&self, ty: &ComponentValType) -> ValueType {
+        let id = match ty {
+            ComponentValType::Primitive(primitive) => return primitive_type(*primitive),
+            ComponentValType::Type(id) => *id,
+        };
+        let boxed = |ty: &ComponentValType| Box::new(self.value(ty));
+        match &self.types[id] {
+            ComponentDefinedType::Primitive(primitive) => primitive_type(*primitive),
+            ComponentDefinedType::Record(record) => {
+                ValueType::Record(record.fields.iter().map(|(name, ty)| (name.to_string(), self.value(ty))).collect())
+            }
+            ComponentDefinedType::Variant(variant) => ValueType::Variant(
+                variant.cases.iter().map(|(name, case)| (name.to_string(), case.ty.as_ref().map(|ty| self.value(ty)))).collect(),
+            ),
+            ComponentDefinedType::List(ty) => ValueType::List(boxed(ty)),
+            ComponentDefinedType::Tuple(tuple) => ValueType::Tuple(tuple.types.iter().map(|ty| self.value(ty)).collect()),
+            ComponentDefinedType::Flags(flags) => ValueType::Flags(flags.iter().map(|f| f.to_string()).collect()),
+            ComponentDefinedType::Enum(cases) => ValueType::Enum(cases.iter().map(|c| c.to_string()).collect()),
+            ComponentDefinedType::Option(ty) => ValueType::Option(boxed(ty)),
+            ComponentDefinedType::Result { ok, err } => ValueType::Result {
+                ok: ok.as_ref().map(boxed),
+                err: err.as_ref().map(boxed),
+            },
+            ComponentDefinedType::Own(resource) => ValueType::Own(self.resource_name(resource.resource())),
+            ComponentDefinedType::Borrow(resource) => ValueType::Borrow(self.resource_name(resource.resource())),
+        }
+    }
+
+    fn resource_name(&self, id: ResourceId) -> String {
+        self.resources.get(&id).cloned().unwrap_or_else(|| "resource".to_string())
+    }
+}
+
+fn primitive_type(primitive: PrimitiveValType) -> ValueType {
+    match primitive {
+        PrimitiveValType::Bool => ValueType::Bool,
+        PrimitiveValType::S8 => ValueType::S8,
+        PrimitiveValType::U8 => ValueType::U8,
+        PrimitiveValType::S16 => ValueType::S16,
+        PrimitiveValType::U16 => ValueType::U16,
+        PrimitiveValType::S32 => ValueType::S32,
+        PrimitiveValType::U32 => ValueType::U32,
+        PrimitiveValType::S64 => ValueType::S64,
+        PrimitiveValType::U64 => ValueType::U64,
+        PrimitiveValType::Float32 => ValueType::Float32,
+        PrimitiveValType::Float64 => ValueType::Float64,
+        PrimitiveValType::Char => ValueType::Char,
+        PrimitiveValType::String => ValueType::String,
+    }
+}
+
+impl FuncType {
+    /// Whether a call passes a resource handle either way.
+    pub fn passes_handles(&self) -> bool {
+        self.params.iter().any(|(_, ty)| ty.holds_handle()) || self.results.iter().any(ValueType::holds_handle)
+    }
+
+    /// Whether a result holds a handle inside a record, list, option or other
+    /// composite rather than being one.
+    pub fn returns_nested_handles(&self) -> bool {
+        self.results.iter().any(|ty| !matches!(ty, ValueType::Own(_) | ValueType::Borrow(_)) && ty.holds_handle())
+    }
+}
+
+impl ValueType {
+    /// Whether a value of this type is, or holds, a resource handle.
+    pub fn holds_handle(&self) -> bool {
+        let optional = |ty: &Option<Box<ValueType>>| ty.as_ref().is_some_and(|ty| ty.holds_handle());
+        match self {
+            ValueType::Own(_) | ValueType::Borrow(_) => true,
+            ValueType::Record(fields) => fields.iter().any(|(_, ty)| ty.holds_handle()),
+            ValueType::Variant(cases) => cases.iter().any(|(_, ty)| ty.as_ref().is_some_and(ValueType::holds_handle)),
+            ValueType::List(ty) | ValueType::Option(ty) => ty.holds_handle(),
+            ValueType::Tuple(types) => types.iter().any(ValueType::holds_handle),
+            ValueType::Result { ok, err } => optional(ok) || optional(err),
+            _ => false,
+        }
+    }
+}
+
+/// e.g. "func(query: string) -> list<record { title: string, url: string }>"
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+        write!(f, "func({})", params.join(", "))?;
+        match self.results.as_slice() {
+            [] => Ok(()),
+            [result] => write!(f, " -> {}", result),
+            results => write!(f, " -> ({})", join(results)),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |ty: &Option<Box<ValueType>>| ty.as_ref().map_or("_".to_string(), |ty| ty.to_string());
+        match self {
+            ValueType::Bool => write!(f, "bool"),
+            ValueType::S8 => write!(f, "s8"),
+            ValueType::U8 => write!(f, "u8"),
+            ValueType::S16 => write!(f, "s16"),
+            ValueType::U16 => write!(f, "u16"),
+            ValueType::S32 => write!(f, "s32"),
+            ValueType::U32 => write!(f, "u32"),
+            ValueType::S64 => write!(f, "s64"),
+            ValueType::U64 => write!(f, "u64"),
+            ValueType::Float32 => write!(f, "float32"),
+            ValueType::Float64 => write!(f, "float64"),
+            ValueType::Char => write!(f, "char"),
+            ValueType::String => write!(f, "string"),
+            ValueType::Record(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+                write!(f, "record {{ {} }}", fields.join(", "))
+            }
+            ValueType::Variant(cases) => {
+                let cases: Vec<String> = cases.iter()
+                    .map(|(name, ty)| ty.as_ref().map_or(name.clone(), |ty| format!("{}({})", name, ty)))
+                    .collect();
+                write!(f, "variant {{ {} }}", cases.join(", "))
+            }
+            ValueType::List(ty) => write!(f, "list<{}>", ty),
+            ValueType::Tuple(types) => write!(f, "tuple<{}>", join(types)),
+            ValueType::Flags(flags) => write!(f, "flags {{ {} }}", flags.join(", ")),
+            ValueType::Enum(cases) => write!(f, "enum {{ {} }}", cases.join(", ")),
+            ValueType::Option(ty) => write!(f, "option<{}>", ty),
+            ValueType::Result { ok: None, err: None } => write!(f, "result"),
+            ValueType::Result { ok, err: None } => write!(f, "result<{}>", optional(ok)),
+            ValueType::Result { ok, err } => write!(f, "result<{}, {}>", optional(ok), optional(err)),
+            ValueType::Own(resource) => write!(f, "{}", resource),
+            ValueType::Borrow(resource) => write!(f, "borrow<{}>", resource),
+        }
+    }
+}
+
+fn join(types: &[ValueType]) -> String {
+    types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(wat: &str) -> ComponentTypes {
+        ComponentTypes::from_binary(&wat::parse_str(wat).unwrap()).unwrap()
+    }
+
+    const DOCS: &str = r#"(component
+        (import "local:docs/search@0.1.0" (instance $search
+            (export "cursor" (type $cursor (sub resource)))
+            (export "open" (func (param "query" string) (result (own $cursor))))
+            (export "[method]cursor.next" (func (param "self" (borrow $cursor)) (result (option string))))))
+        (import "local:docs/types" (instance
+            (type $level (enum "low" "high"))
+            (export "level" (type (eq $level)))))
+        (import "ask" (func (param "n" u32) (result u32)))
+        (core module $m (func (export "run") (param i32) (result i32) local.get 0))
+        (core instance $i (instantiate $m))
+        (func $run (param "x" s32) (result s32) (canon lift (core func $i "run")))
+        (export "run" (func $run)))"#;
+
+    #[test]
+    fn reads_instances_functions_and_resources() {
+        let types = types(DOCS);
+        let Some(Item::Instance { functions, resources }) = types.import("local:docs/search@0.1.0") else {
+            panic!("search is not an instance import");
+        };
+        assert_eq!(resources, &["cursor"]);
+        assert_eq!(functions["open"], FuncType {
+            params: vec![("query".to_string(), ValueType::String)],
+            results: vec![ValueType::Own("cursor".to_string())],
+        });
+        assert_eq!(functions["[method]cursor.next"].results, [ValueType::Option(Box::new(ValueType::String))]);
+        assert_eq!(
+            types.import("ask"),
+            Some(&Item::Func(FuncType { params: vec![("n".to_string(), ValueType::U32)], results: vec![ValueType::U32] }))
+        );
+        assert!(matches!(types.export("run"), Some(Item::Func(f)) if f.params[0].1 == ValueType::S32));
+    }
+
+    #[test]
+    fn lookups_may_leave_out_the_version() {
+        let types = types(DOCS);
+        assert!(types.import("local:docs/search").is_some());
+        assert!(types.import("local:docs/other").is_none());
+        assert!(types.export("ask").is_none());
+    }
+
+    #[test]
+    fn type_only_imports_need_no_provider() {
+        assert_eq!(types(DOCS).linked_imports(), ["ask", "local:docs/search@0.1.0"]);
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_component() {
+        assert!(ComponentTypes::from_binary(b"not wasm").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::catalogue::split_function;
+use crate::provenance::{source_side, Provenance};
+use crate::{
+    Capability, CapabilitySource, Catalogue, CutPoint, Environment, SafetyViolation, Trust, ViolationType, Workflow, WorkflowEdge,
+    WorkflowStep, DATA_LABELS,
+};
+
+/// Step ids referenced as `{{ <step>.output }}` in a step input template.
+pub fn template_refs(template: &str) -> Vec<&str> {
+    let mut refs = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else { break };
+        let path = rest[start + 2..start + 2 + len].trim();
+        if let Some(step_id) = path.strip_suffix(".output") {
+            refs.push(step_id);
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    refs
+}
+
+/// Builds the taint graph of a workflow (edges run from the step producing an
+/// output to each step whose input template reads it) and reports every step on an
+/// Exfiltration-capable component that receives Internal Data, when Untrusted Input
+/// is also present on that path.
+///
+/// Step outputs carry their inputs' labels plus the labels of the component (and
+/// function) that produced them, so a leak is found even when the three capabilities
+/// never meet in a single component. Outputs of declared sanitisers carry no labels.
+pub(crate) fn check_workflow(
+    workflow: &Workflow,
+    component_caps: &HashMap<&str, HashSet<Capability>>,
+    catalogue: &Catalogue,
+    env: &Environment,
+    trust: &Trust,
+    provenance: &Provenance,
+) -> Vec<SafetyViolation> {
+    let mut graph = DiGraph::<usize, ()>::new();
+    let mut step_nodes: HashMap<&str, NodeIndex> = HashMap::new();
+    for (i, step) in workflow.steps.iter().enumerate() {
+        let idx = graph.add_node(i);
+        // Steps only see outputs of steps that ran before them.
+        for step_id in step.input.as_deref().map(template_refs).unwrap_or_default() {
+            if let Some(&producer) = step_nodes.get(step_id) {
+                graph.update_edge(producer, idx, ());
+            }
+        }
+        step_nodes.insert(step.id.as_str(), idx);
+    }
+
+    // Steps are added in execution order, so node order is a topological order.
+    let mut taint = Taint {
+        graph: &graph,
+        workflow,
+        component_caps,
+        provenance,
+        step_caps: Vec::new(),
+        function_caps: Vec::new(),
+        outputs: Vec::new(),
+    };
+    let mut flagged = Vec::new();
+    for idx in graph.node_indices() {
+        let step = &workflow.steps[graph[idx]];
+
+        let mut input_labels = HashSet::new();
+        for producer in graph.neighbors_directed(idx, Direction::Incoming) {
+            input_labels.extend(taint.outputs[producer.index()].iter().copied());
+        }
+
+        let mut step_caps = component_caps.get(step.component.as_str()).cloned().unwrap_or_default();
+        // A root function is classified as "<package>#<function>".
+        let (interface, function) = split_function(&step.function).unwrap_or(("", step.function.as_str()));
+        let key = if interface.is_empty() {
+            env.catalogue_key(&format!("{}.{}", step.component, function))
+        } else {
+            Some(interface.to_string())
+        };
+        let function = [function.to_string()];
+        let function_caps = key
+            .and_then(|key| catalogue.capabilities(&key, Some(&function)).ok())
+            .unwrap_or_default();
+        step_caps.extend(function_caps.iter().copied());
+
+        if step_caps.contains(&Capability::Exfiltration)
+            && input_labels.contains(&Capability::InternalData)
+            && (input_labels.contains(&Capability::UntrustedInput) || step_caps.contains(&Capability::UntrustedInput))
+        {
+            flagged.push(idx);
+        }
+
+        let labels = if trust.declassifies(&step.component, interface, Some(&function)) {
+            HashSet::new()
+        } else {
+            let mut labels = input_labels;
+            labels.extend(step_caps.iter().copied().filter(|c| DATA_LABELS.contains(c)));
+            labels
+        };
+        taint.step_caps.push(step_caps);
+        taint.function_caps.push(function_caps);
+        taint.outputs.push(labels);
+    }
+
+    flagged.into_iter().map(|idx| {
+        let step = &workflow.steps[graph[idx]];
+        let mut violation = SafetyViolation::new(
+            &step.component,
+            ViolationType::DataflowTrifecta,
+            format!(
+                "Workflow step '{}' sends Internal Data to Exfiltration-capable component '{}' ({}) with Untrusted Input on the path.",
+                step.id, step.component, step.function
+            ),
+        );
+        violation.capabilities = vec![Capability::UntrustedInput, Capability::InternalData, Capability::Exfiltration];
+        violation.sources = violation.capabilities.iter().map(|&capability| taint.source(idx, capability)).collect();
+        violation.cut = taint.cut(idx);
+        violation
+    }).collect()
+}
+
+/// The labelled workflow graph, for explaining the steps it flags.
+struct Taint<'a> {
+    graph: &'a DiGraph<usize, ()>,
+    workflow: &'a Workflow,
+    component_caps: &'a HashMap<&'a str, HashSet<Capability>>,
+    provenance: &'a Provenance<'a>,
+    /// By node index: each step's own capabilities, the part of them its function
+    /// has in the catalogue, and the labels on its output.
+    step_caps: Vec<HashSet<Capability>>,
+    function_caps: Vec<HashSet<Capability>>,
+    outputs: Vec<HashSet<Capability>>,
+}
+
+impl Taint<'_> {
+    fn step(&self, idx: NodeIndex) -> &WorkflowStep {
+        &self.workflow.steps[self.graph[idx]]
+    }
+
+    /// Where the flagged step `target` got `capability`: Exfiltration and its own
+    /// Untrusted Input from itself, labels on its input along the steps they came
+    /// through, and then from the wires of the step that introduced them.
+    fn source(&self, target: NodeIndex, capability: Capability) -> CapabilitySource {
+        let from_input = match capability {
+            Capability::Exfiltration => false,
+            Capability::UntrustedInput => !self.step_caps[target.index()].contains(&capability),
+            _ => true,
+        };
+        let path = if from_input { self.label_path(target, capability) } else { vec![target] };
+        let origin = self.step(path[0]).component.as_str();
+        let wires = match self.component_caps.get(origin) {
+            Some(caps) if caps.contains(&capability) => self.provenance.shortest_chain(origin, capability).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        CapabilitySource {
+            capability,
+            wires,
+            steps: path.into_iter().map(|idx| self.step(idx).id.clone()).collect(),
+        }
+    }
+
+    /// The fewest steps from one that introduces `label` to `target`, through
+    /// outputs that carry it.
+    fn label_path(&self, target: NodeIndex, label: Capability) -> Vec<NodeIndex> {
+        // Breadth-first backwards; `next` maps each step to the one it feeds on the
+        // way to `target`.
+        let mut next: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([target]);
+        while let Some(node) = queue.pop_front() {
+            for producer in self.graph.neighbors_directed(node, Direction::Incoming) {
+                if !self.outputs[producer.index()].contains(&label) || next.contains_key(&producer) {
+                    continue;
+                }
+                next.insert(producer, node);
+                if self.step_caps[producer.index()].contains(&label) {
+                    let (mut path, mut step) = (vec![producer], producer);
+                    while let Some(&after) = next.get(&step) {
+                        path.push(after);
+                        step = after;
+                    }
+                    return path;
+                }
+                queue.push_back(producer);
+            }
+        }
+        vec![target]
+    }
+
+    /// The smallest removal that clears the violation at `target`: the fewest
+    /// workflow edges keeping Internal Data (or Untrusted Input) from it, or the
+    /// fewest wires taking Exfiltration from its component. Empty when none can,
+    /// e.g. the step's function is itself catalogued as Exfiltration and takes
+    /// Untrusted Input on its own.
+    fn cut(&self, target: NodeIndex) -> Vec<CutPoint> {
+        let mut candidates = vec![self.edge_cut(target, Capability::InternalData)];
+        if !self.step_caps[target.index()].contains(&Capability::UntrustedInput) {
+            candidates.push(self.edge_cut(target, Capability::UntrustedInput));
+        }
+        if !self.function_caps[target.index()].contains(&Capability::Exfiltration) {
+            let component = self.step(target).component.as_str();
+            let wires = self.provenance.cut_for(component, Capability::Exfiltration);
+            candidates.push(wires.map(|wires| wires.into_iter().map(CutPoint::Wire).collect()));
+        }
+        candidates.into_iter().flatten().min_by_key(|cut| cut.len()).unwrap_or_default()
+    }
+
+    /// Minimum edge cut between the steps that introduce `label` and `target`, as
+    /// unit capacity max-flow over the edges whose producer's output carries it.
+    fn edge_cut(&self, target: NodeIndex, label: Capability) -> Option<Vec<CutPoint>> {
+        let source = self.graph.node_count();
+        let unbounded = self.graph.edge_count() as u32 + 1;
+        let mut capacity = vec![vec![0u32; source + 1]; source + 1];
+        for idx in self.graph.node_indices().filter(|&idx| idx != target) {
+            if self.step_caps[idx.index()].contains(&label) && self.outputs[idx.index()].contains(&label) {
+                capacity[source][idx.index()] = unbounded;
+            }
+        }
+        let edges: Vec<(NodeIndex, NodeIndex)> = self.graph.edge_indices()
+            .filter_map(|edge| self.graph.edge_endpoints(edge))
+            .filter(|(from, _)| self.outputs[from.index()].contains(&label))
+            .collect();
+        for &(from, to) in &edges {
+            capacity[from.index()][to.index()] = 1;
+        }
+
+        let reachable = source_side(&mut capacity, source, target.index(), unbounded)?;
+        let cut: Vec<CutPoint> = edges.into_iter()
+            .filter(|(from, to)| reachable[from.index()] && !reachable[to.index()])
+            .map(|(from, to)| CutPoint::Edge(WorkflowEdge {
+                from: self.step(from).id.clone(),
+                to: self.step(to).id.clone(),
+            }))
+            .collect();
+        (!cut.is_empty()).then_some(cut)
+    }
+}
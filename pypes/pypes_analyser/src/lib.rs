@@ -1,7 +1,24 @@
-use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::graph::DiGraph;
+use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+mod bandwidth;
+mod catalogue;
+mod compat;
+mod dataflow;
+mod provenance;
+mod report;
+mod settings;
+pub mod component_types;
+pub mod wit_loader;
+
+pub use bandwidth::{channels, format_bits, result_bits, type_bits, Channel};
+pub use catalogue::{is_root_export, split_function, Catalogue, InterfaceEntry, CATALOGUE_VERSION};
+pub use report::{Report, REPORT_VERSION};
+use component_types::{ComponentTypes, Item};
+use wit_loader::WitLoader;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Blueprint {
@@ -10,8 +27,62 @@ pub struct Blueprint {
     pub wiring: HashMap<String, Connection>,
     #[serde(default)]
     pub workflow: Option<Workflow>,
+    #[serde(default)]
+    pub trust: Trust,
+    /// Middleware implemented as WASM components: name -> component path. A wire
+    /// lists a guard in `middleware` like any built-in.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub guards: BTreeMap<String, String>,
+}
+
+/// Where untrusted data enters the system and where taint is allowed to stop.
+///
+/// ```toml
+/// [trust]
+/// untrusted_inputs = ["leaky_agent"]
+///
+/// [[trust.sanitisers]]
+/// component = "llm"
+/// functions = ["local:calendar-privacy/llm-api.predict-state"]
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Trust {
+    /// Components that receive user prompts or other external input.
+    #[serde(default)]
+    pub untrusted_inputs: Vec<String>,
+    #[serde(default)]
+    pub sanitisers: Vec<Sanitiser>,
+}
+
+/// A declassifier: data labels (Internal Data, Untrusted Input) do not flow through
+/// its outputs. Meant for typed, low-bandwidth results such as an enum.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Sanitiser {
+    pub component: String,
+    /// Qualified "interface.function" names. Every export is a sanitiser if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub functions: Option<Vec<String>>,
+}
+
+impl Trust {
+    /// Whether calling `functions` of `interface` on `component` declassifies the
+    /// result. `functions: None` means the whole interface is reachable; an empty
+    /// `interface` means `functions` are root functions.
+    pub fn declassifies(&self, component: &str, interface: &str, functions: Option<&[String]>) -> bool {
+        self.sanitisers.iter().filter(|s| s.component == component).any(|s| match (&s.functions, functions) {
+            (None, _) => true,
+            (Some(declared), Some(called)) => called.iter().all(|f| {
+                // Root functions are declared by bare name.
+                let qualified = if interface.is_empty() { f.clone() } else { format!("{}.{}", interface, f) };
+                declared.contains(&qualified)
+            }),
+            (Some(_), None) => false,
+        })
+    }
 }
 
+// A blueprint has a handful of wires, so the size of the configured form is moot.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub enum Connection {
@@ -20,16 +91,354 @@ pub enum Connection {
         provider: String,
         #[serde(default)]
         middleware: Vec<String>,
+        // Subset of the interface's functions exposed over this wire (all if absent).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        functions: Option<Vec<String>>,
+        // Only meaningful on `host.wasi:filesystem/*` wires.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preopen: Option<Preopen>,
+        // Only meaningful on `host.wasi:http/outgoing-handler` wires.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        http: Option<HttpGrant>,
+        // Only meaningful on `host.wasi:cli/environment` wires.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        env: Option<EnvGrant>,
+        // Most bits one call may return, judged from the provider's WIT result types.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_bits: Option<u32>,
+        // Settings for the `dlp` middleware on this wire.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dlp: Option<DlpConfig>,
+        // Quotas for the `budget` middleware on this wire.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        budget: Option<BudgetConfig>,
+        // Per-function refinements for the `validate` middleware on this wire.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        validate: Option<BTreeMap<String, FunctionRules>>,
+        // Token bucket for the `rate_limit` middleware on this wire.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rate_limit: Option<RateLimitConfig>,
+        // Wall-clock limit on one call, for the `timeout` middleware on this wire.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_ms: Option<u64>,
+        // A data diode: calls that would pass a resource handle either way are refused.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        diode: bool,
+        // Anything else: settings for registered or guard middleware, by name.
+        #[serde(flatten)]
+        settings: BTreeMap<String, toml::Value>,
     },
 }
 
 impl Connection {
+    /// A wire to `provider` through `middleware`, with every other setting left out.
+    pub fn through(provider: &str, middleware: &[&str]) -> Self {
+        Connection::Configured {
+            provider: provider.to_string(),
+            middleware: middleware.iter().map(|m| m.to_string()).collect(),
+            functions: None,
+            preopen: None,
+            http: None,
+            env: None,
+            max_bits: None,
+            dlp: None,
+            budget: None,
+            validate: None,
+            rate_limit: None,
+            timeout_ms: None,
+            diode: false,
+            settings: BTreeMap::new(),
+        }
+    }
+
     pub fn provider(&self) -> &str {
         match self {
             Connection::Simple(s) => s,
             Connection::Configured { provider, .. } => provider,
         }
     }
+
+    /// Middleware names, outermost first.
+    pub fn middleware(&self) -> &[String] {
+        match self {
+            Connection::Simple(_) => &[],
+            Connection::Configured { middleware, .. } => middleware,
+        }
+    }
+
+    /// Functions granted over this wire, or `None` when the whole interface is.
+    pub fn functions(&self) -> Option<&[String]> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { functions, .. } => functions.as_deref(),
+        }
+    }
+
+    pub fn is_function_granted(&self, function: &str) -> bool {
+        self.functions().is_none_or(|fs| fs.iter().any(|f| f == function))
+    }
+
+    pub fn preopen(&self) -> Option<&Preopen> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { preopen, .. } => preopen.as_ref(),
+        }
+    }
+
+    pub fn http(&self) -> Option<&HttpGrant> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { http, .. } => http.as_ref(),
+        }
+    }
+
+    pub fn env(&self) -> Option<&EnvGrant> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { env, .. } => env.as_ref(),
+        }
+    }
+
+    pub fn dlp(&self) -> Option<&DlpConfig> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { dlp, .. } => dlp.as_ref(),
+        }
+    }
+
+    pub fn budget(&self) -> Option<&BudgetConfig> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { budget, .. } => budget.as_ref(),
+        }
+    }
+
+    pub fn validate(&self) -> Option<&BTreeMap<String, FunctionRules>> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { validate, .. } => validate.as_ref(),
+        }
+    }
+
+    pub fn rate_limit(&self) -> Option<&RateLimitConfig> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { rate_limit, .. } => rate_limit.as_ref(),
+        }
+    }
+
+    pub fn timeout_ms(&self) -> Option<u64> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { timeout_ms, .. } => *timeout_ms,
+        }
+    }
+
+    /// The wire's `<name> = { ... }` entry for middleware that is not built in.
+    pub fn settings(&self, name: &str) -> Option<&toml::Value> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { settings, .. } => settings.get(name),
+        }
+    }
+
+    /// Names of every entry `settings` can return, which should each name
+    /// middleware on the wire; anything else is a misspelt or misplaced key.
+    pub fn setting_names(&self) -> Vec<&str> {
+        match self {
+            Connection::Simple(_) => Vec::new(),
+            Connection::Configured { settings, .. } => settings.keys().map(String::as_str).collect(),
+        }
+    }
+
+    pub fn max_bits(&self) -> Option<u32> {
+        match self {
+            Connection::Simple(_) => None,
+            Connection::Configured { max_bits, .. } => *max_bits,
+        }
+    }
+
+    pub fn is_diode(&self) -> bool {
+        match self {
+            Connection::Simple(_) => false,
+            Connection::Configured { diode, .. } => *diode,
+        }
+    }
+}
+
+/// A host directory made visible to a component at `guest_path`.
+/// e.g. `preopen = { host_path = "~/calendars", guest_path = "/calendars", mode = "read-only" }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Preopen {
+    pub host_path: String,
+    pub guest_path: String,
+    #[serde(default)]
+    pub mode: AccessMode,
+}
+
+/// Outbound requests a component may make. Anything not listed is refused.
+/// e.g. `http = { allowed_hosts = ["api.search.example"], allowed_methods = ["GET"], max_body_bytes = 0 }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HttpGrant {
+    // Exact host names, or "*.example.com" for any subdomain, ignoring case. A
+    // "https://" (or "http://") prefix allows only that scheme.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default = "default_http_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<u64>,
+}
+
+fn default_http_methods() -> Vec<String> {
+    vec!["GET".to_string()]
+}
+
+/// Environment variables a component sees. Nothing else of the host's environment
+/// is visible.
+/// e.g. `env = { inherit = ["TZ", "LANG"], set = { MODE = "demo" } }`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct EnvGrant {
+    // Host variables passed on by name; those the host has not set are left out.
+    #[serde(default)]
+    pub inherit: Vec<String>,
+    // Fixed name/value pairs, set whatever the host has.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
+/// What the `dlp` middleware looks for in call arguments, and what it does on a match.
+/// e.g. `dlp = { detectors = ["card-number", "email"], patterns = { ticket = "TKT-[0-9]+" }, action = "redact" }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DlpConfig {
+    // Built-in detectors; all of them if absent.
+    #[serde(default = "default_dlp_detectors")]
+    pub detectors: Vec<DlpDetector>,
+    // Custom detectors: name -> regex.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub patterns: BTreeMap<String, String>,
+    #[serde(default)]
+    pub action: DlpAction,
+}
+
+impl Default for DlpConfig {
+    fn default() -> Self {
+        Self { detectors: default_dlp_detectors(), patterns: BTreeMap::new(), action: DlpAction::default() }
+    }
+}
+
+fn default_dlp_detectors() -> Vec<DlpDetector> {
+    vec![DlpDetector::CardNumber, DlpDetector::Email, DlpDetector::PhoneNumber, DlpDetector::Iban]
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DlpDetector {
+    CardNumber,
+    Email,
+    PhoneNumber,
+    Iban,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DlpAction {
+    /// Fail the call.
+    #[default]
+    Reject,
+    /// Replace each match with `[REDACTED:<detector>]` and let the call through.
+    Redact,
+}
+
+/// Quotas for the `budget` middleware, over the JSON-serialized call arguments.
+/// Limits left out are not enforced.
+/// e.g. `budget = { max_call_bytes = 1024, max_total_bytes = 65536, max_calls = 100 }`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+pub struct BudgetConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_call_bytes: Option<u64>,
+    // Across every call over the wire during one run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_calls: Option<u64>,
+}
+
+/// Token bucket for the `rate_limit` middleware: up to `burst` calls back to back,
+/// refilled at `per_second` calls a second.
+/// e.g. `rate_limit = { per_second = 0.5, burst = 3, on_limit = "wait" }`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct RateLimitConfig {
+    pub per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    #[serde(default)]
+    pub on_limit: OnLimit,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnLimit {
+    /// Fail the call.
+    #[default]
+    Reject,
+    /// Hold the call until a token is free.
+    Wait,
+}
+
+/// Refinements the `validate` middleware checks on one function's arguments (by
+/// WIT parameter name) and result. A `result` rule needs a function with exactly
+/// one result.
+///
+/// ```toml
+/// [wiring."agent.local:calendar-privacy/search-api".validate.search]
+/// args.query = { max_length = 200, pattern = "^[a-z ]+$" }
+/// result = { max_length = 10, items = { fields.url = { pattern = "^https://" } } }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FunctionRules {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub args: BTreeMap<String, Refinement>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Refinement>,
+}
+
+/// Constraints on a value beyond its WIT type. Each applies only to the kinds of
+/// value it makes sense for; the rest are ignored. An option is checked by its payload.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Refinement {
+    // Characters of a string, or elements of a list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    // Regex a string must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    // Inclusive bounds for integers and floats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    // Enum or variant cases that may be used, or flags that may be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    // Applied to every list element.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Refinement>>,
+    // Applied to record fields by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Refinement>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessMode {
+    #[default]
+    ReadOnly,
+    ReadWrite,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -44,37 +453,228 @@ pub struct WorkflowStep {
     pub function: String,
     #[serde(default)]
     pub input: Option<String>,
+    /// Most bits this step may receive from any one earlier step's output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bits: Option<u32>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ViolationType {
     LethalTrifecta, // Untrusted + Internal + Exfiltration
     DeadlyDuo,      // Untrusted + Destructive
+    UnclassifiedInterface, // Wired interface missing from the capability catalogue
+    DataflowTrifecta, // Internal data reaches an Exfiltration step through workflow outputs
+    BandwidthExceeded, // A wire or workflow edge can carry more bits than its budget
+    TypeMismatch,      // A consumer imports a function the provider does not export with that type
+    MisconfiguredWire, // A wire carries settings that cannot take effect on it
+}
+
+/// Labels describing the data a component handles, as opposed to its authority.
+/// These are what a sanitiser stops.
+pub(crate) const DATA_LABELS: [Capability; 2] = [Capability::InternalData, Capability::UntrustedInput];
+
+/// How much of a provider's own capabilities reach its consumer over a wire.
+/// Ordered so that where several wires join the same pair, the widest wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Flow {
+    /// Every function granted is a declared sanitiser or catalogued as `[]`:
+    /// authority flows, data labels stop.
+    Authority,
+    Everything,
+}
+
+impl Flow {
+    pub(crate) fn carries(self, capability: Capability) -> bool {
+        match self {
+            Flow::Authority => !DATA_LABELS.contains(&capability),
+            Flow::Everything => true,
+        }
+    }
+}
+
+fn wire_flow(blueprint: &Blueprint, catalogue: &Catalogue, env: &Environment, connection: &Connection) -> Flow {
+    let provider_key = connection.provider();
+    let provider = provider_key.split('.').next().unwrap_or(provider_key);
+    let export = catalogue::interface_of(provider_key);
+    let root = [export.to_string()];
+    let callable = callable_functions(connection, env);
+    let (interface, functions) = if catalogue::is_root_export(export) {
+        ("", Some(&root[..]))
+    } else {
+        (export, callable.as_deref())
+    };
+    let passes_no_data = env.catalogue_key(provider_key)
+        .is_some_and(|key| catalogue.passes_no_data(&key, callable.as_deref()));
+    if passes_no_data || blueprint.trust.declassifies(provider, interface, functions) {
+        Flow::Authority
+    } else {
+        Flow::Everything
+    }
+}
+
+/// The functions a consumer can call over `connection`: its `functions` grant
+/// (`None` for all of them). A diode wire refuses every call passing a resource
+/// handle, so where the provider's binary shows which functions do, they are left
+/// out.
+fn callable_functions(connection: &Connection, env: &Environment) -> Option<Vec<String>> {
+    let granted = connection.functions().map(<[String]>::to_vec);
+    let provider_key = connection.provider();
+    let provider = provider_key.split('.').next().unwrap_or(provider_key);
+    let export = env.types.get(provider).and_then(|types| types.export(catalogue::interface_of(provider_key)));
+    let (true, Some(Item::Instance { functions, .. })) = (connection.is_diode(), export) else {
+        return granted;
+    };
+    if !functions.values().any(|func| func.passes_handles()) {
+        return granted;
+    }
+    Some(functions.iter()
+        .filter(|(name, func)| connection.is_function_granted(name) && !func.passes_handles())
+        .map(|(name, _)| name.clone())
+        .collect())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Clone)]
 pub struct SafetyViolation {
     pub component: String,
     pub violation: ViolationType,
     pub details: String,
+    /// Everything the component can do or see once the graph is propagated.
+    pub capabilities: Vec<Capability>,
+    /// Where each of those capabilities came from.
+    pub sources: Vec<CapabilitySource>,
+    /// Fewest wires or workflow edges whose removal clears this violation. Empty
+    /// when no removal can, or the violation is not about capabilities meeting.
+    pub cut: Vec<CutPoint>,
+}
+
+impl SafetyViolation {
+    /// Capabilities and sources are filled in by `verify` once propagation is done.
+    pub(crate) fn new(component: &str, violation: ViolationType, details: String) -> Self {
+        Self {
+            component: component.to_string(),
+            violation,
+            details,
+            capabilities: Vec::new(),
+            sources: Vec::new(),
+            cut: Vec::new(),
+        }
+    }
+}
+
+/// A single `wiring` entry: `"consumer.import" = "provider.export"`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Wire {
+    pub consumer: String,
+    pub provider: String,
+}
+
+impl std::fmt::Display for Wire {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.consumer, self.provider)
+    }
+}
+
+/// A workflow step's input reading an earlier step's output, `{{ from.output }}`
+/// in the input template of `to`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct WorkflowEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Something whose removal helps clear a violation.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum CutPoint {
+    Wire(Wire),
+    Edge(WorkflowEdge),
+}
+
+impl std::fmt::Display for CutPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CutPoint::Wire(wire) => write!(f, "{}", wire),
+            CutPoint::Edge(edge) => write!(f, "{{{{ {}.output }}}} from the input of step '{}'", edge.from, edge.to),
+        }
+    }
+}
+
+/// The shortest chain of wires through which a component obtained a capability,
+/// starting at the component itself and ending at the wire that grants it. An empty
+/// chain means the blueprint declares the component an untrusted input, or, for a
+/// workflow step, that the catalogue gives the step's function the capability.
+///
+/// For dataflow violations, `steps` is the path the capability's label took through
+/// the workflow, from the step that introduced it to the flagged step; `wires` then
+/// explains the first of those.
+#[derive(Debug, Serialize, Clone)]
+pub struct CapabilitySource {
+    pub capability: Capability,
+    pub wires: Vec<Wire>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<String>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Capability {
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
     UntrustedInput, // User prompt or Web results
     InternalData,   // Calendar, Files
     Exfiltration,   // HTTP, Network
     Destructive,    // Delete, Write
-    Proposal,       // Human Verification (Safe)
+    Proposal,       // Human Verification (Safe only behind the `approval` middleware)
 }
 
-pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
+/// Middleware every pypes runtime provides. Blueprints may also name their own
+/// `[guards]` and, through `Environment::middleware`, middleware an embedding
+/// crate registers.
+pub const BUILTIN_MIDDLEWARE: [&str; 9] =
+    ["approval", "audit", "budget", "dlp", "logging", "noop", "rate_limit", "timeout", "validate"];
+
+/// What verification knows about a blueprint's components beyond the blueprint.
+#[derive(Default)]
+pub struct Environment {
+    /// WIT by component name, used to size channels with a `max_bits` budget.
+    /// Components without WIT cannot meet a budget.
+    pub wit: HashMap<String, WitLoader>,
+    /// Imports and exports of each component binary, which every wire between
+    /// components is type-checked against.
+    pub types: HashMap<String, ComponentTypes>,
+    /// Components that cannot be inspected yet, such as remote components not
+    /// fetched under `--verify-only` or local binaries not built yet. Checks that need their contents are left to
+    /// the run that fetches them.
+    pub pending: BTreeSet<String>,
+    /// Middleware registered beyond `BUILTIN_MIDDLEWARE`, which wires may name.
+    pub middleware: BTreeSet<String>,
+}
+
+impl Environment {
+    /// What the catalogue classifies the export of `"provider.export"` under: the
+    /// interface itself, or "<package>#<function>" for a root function, with the
+    /// package named by the provider's WIT. `None` for a root function of a
+    /// provider without WIT.
+    pub fn catalogue_key(&self, provider_key: &str) -> Option<String> {
+        let provider = provider_key.split('.').next().unwrap_or(provider_key);
+        let export = catalogue::interface_of(provider_key);
+        if !catalogue::is_root_export(export) {
+            return Some(export.to_string());
+        }
+        self.wit.get(provider).map(|wit| catalogue::root_key(&wit.package(), export))
+    }
+}
+
+pub fn verify(
+    blueprint: &Blueprint,
+    catalogue: &Catalogue,
+    env: &Environment,
+) -> Result<(), Vec<SafetyViolation>> {
     let mut violations = Vec::new();
 
     // 1. Build Graph
     // Nodes are components (including "host").
-    // Edges are dependencies (Consumer -> Provider).
-    let mut graph = DiGraph::<&str, ()>::new();
+    // Edges are dependencies (Consumer -> Provider), weighted with how much of the
+    // provider's own capabilities the wires along them pass on.
+    let mut graph = DiGraph::<&str, Flow>::new();
     let mut node_map = HashMap::new();
 
     // Add components
@@ -91,15 +691,21 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
     // Add edges from wiring
     // wiring: "consumer.import" = "provider.export"
     // wiring: "consumer.import" = "provider.export" OR { provider = "...", ... }
-    for (consumer_key, connection) in &blueprint.wiring {
+    // Sorted so violations do not depend on hash order.
+    let mut wiring: Vec<(&String, &Connection)> = blueprint.wiring.iter().collect();
+    wiring.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    for &(consumer_key, connection) in &wiring {
         let consumer_name = consumer_key.split('.').next().unwrap_or(consumer_key);
         let provider_key = connection.provider();
         let provider_name = provider_key.split('.').next().unwrap_or(provider_key);
 
+        let flow = wire_flow(blueprint, catalogue, env, connection);
+
         if let (Some(&c_idx), Some(&p_idx)) = (node_map.get(consumer_name), node_map.get(provider_name)) {
             // Edge: Consumer depends on Provider
-            if !graph.contains_edge(c_idx, p_idx) {
-                graph.add_edge(c_idx, p_idx, ());
+            match graph.find_edge(c_idx, p_idx) {
+                Some(edge) => graph[edge] = graph[edge].max(flow),
+                None => { graph.add_edge(c_idx, p_idx, flow); }
             }
         }
     }
@@ -115,35 +721,89 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
     // Map components to the capabilities they *consume*.
     
     let mut component_caps: HashMap<&str, HashSet<Capability>> = HashMap::new();
+    // Kept to explain violations once the fixpoint is reached.
+    let mut wire_facts = Vec::new();
     
     // Initialize empty sets
     for name in blueprint.components.keys() {
         component_caps.insert(name.as_str(), HashSet::new());
     }
-    // Assume the 'main' component (if there is one?) gets UntrustedInput (User Prompt).
-    // For now, we'll assume ANY component that acts as a "logic" node might receive user input?
-    // Let's refine: The user usually talks to ONE entrypoint. 
-    // We'll mark 'host' as safe, but interfaces from host might be dangerous.
+    // Components the blueprint declares as receiving user prompts or external input.
+    for name in &blueprint.trust.untrusted_inputs {
+        if let Some(set) = component_caps.get_mut(name.as_str()) {
+            set.insert(Capability::UntrustedInput);
+        }
+    }
 
     // 3. Analyze Wiring to seed capabilities
-    for (consumer_key, connection) in &blueprint.wiring {
+    for &(consumer_key, connection) in &wiring {
         let consumer_name = consumer_key.split('.').next().unwrap();
         // provider could be "host" or another component
         // let provider_name = provider_key.split('.').next().unwrap();
 
-        let caps = infer_capabilities(connection.provider());
+        let provider_key = connection.provider();
+        let provider_name = provider_key.split('.').next().unwrap_or(provider_key);
+        let mut facts = provenance::WireFacts {
+            wire: Wire { consumer: consumer_key.clone(), provider: provider_key.to_string() },
+            consumer: consumer_name,
+            provider: provider_name,
+            flow: wire_flow(blueprint, catalogue, env, connection),
+            grants: HashSet::new(),
+        };
+
+        // Only the functions reachable over this wire contribute capabilities.
+        let Some(interface) = env.catalogue_key(provider_key) else {
+            wire_facts.push(facts);
+            violations.push(SafetyViolation::new(
+                consumer_name,
+                ViolationType::UnclassifiedInterface,
+                format!(
+                    "Root function '{}' (wired into '{}') cannot be classified: no WIT names the package of '{}'.",
+                    catalogue::interface_of(provider_key), consumer_name, provider_name
+                ),
+            ));
+            continue;
+        };
+        let mut caps = match catalogue.capabilities(&interface, callable_functions(connection, env).as_deref()) {
+            Ok(caps) => caps,
+            Err(unclassified) => {
+                wire_facts.push(facts);
+                violations.push(SafetyViolation::new(
+                    consumer_name,
+                    ViolationType::UnclassifiedInterface,
+                    format!("{} (wired into '{}') is not in the capability catalogue.", unclassified, consumer_name),
+                ));
+                continue;
+            }
+        };
+        // A writable directory grant can destroy data; a read-only one only exposes it.
+        if connection.preopen().is_some_and(|p| p.mode == AccessMode::ReadWrite) {
+            caps.insert(Capability::Destructive);
+        }
+        // A proposal is only safe if a human sees it first; without `approval` on the
+        // wire it goes straight through and acts like the change it proposes. Host
+        // wires are linked directly and run no middleware, so approval there is void.
+        let approved = provider_name != "host" && connection.middleware().iter().any(|m| m == "approval");
+        if caps.contains(&Capability::Proposal) && !approved {
+            caps.insert(Capability::Destructive);
+        }
         
         if let Some(set) = component_caps.get_mut(consumer_name) {
-            for cap in caps {
+            for &cap in &caps {
                 set.insert(cap);
             }
         }
+        facts.grants = caps;
+        wire_facts.push(facts);
     }
 
     // 4. Propagate Transitive Capabilities
     // If A depends on B, A gains B's capabilities?
     // YES. If A calls B, and B can Read Calendar, A can effectively Read Calendar (by asking B).
-    // (This is a conservative approximation: B might sanitize, but for plumbing safety we assume worst case).
+    // (This is a conservative approximation: B might sanitize, but for plumbing safety we assume worst case,
+    // unless B is a declared sanitiser for everything A can call, in which case only B's authority
+    // (Exfiltration, Destructive, ...) flows to A and its data labels stop at the boundary. The
+    // same holds when the catalogue lists everything A can call as `[]`).
     
     let mut changed = true;
     while changed {
@@ -154,11 +814,14 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
         for (consumer_name, consumer_caps) in component_caps.iter_mut() {
             if let Some(&c_idx) = node_map.get(consumer_name) {
                 // Find all providers for this consumer
-                let neighbors = graph.neighbors_directed(c_idx, Direction::Outgoing);
-                for p_idx in neighbors {
-                    let provider_name = graph[p_idx];
+                for edge in graph.edges_directed(c_idx, Direction::Outgoing) {
+                    let provider_name = graph[edge.target()];
+                    let flow = *edge.weight();
                     if let Some(provider_caps_set) = current_caps.get(provider_name) {
                         for &cap in provider_caps_set {
+                            if !flow.carries(cap) {
+                                continue;
+                            }
                             if consumer_caps.insert(cap) {
                                 changed = true;
                             }
@@ -171,12 +834,8 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
     
     // 5. Check Violations
     for (name, caps) in &component_caps {
-        // Assume Entrypoint gets UntrustedInput implicitly? 
-        // Or should we rely on explicit wiring?
-        // Let's assume if it has Exfiltration + Internal, it's ALREADY bad if we assume User Input is always present or flows freely?
-        // Actually, "Lethal Trifecta" requires Untrusted Input.
-        // Let's assume "UntrustedInput" comes from:
-        // 1. Explicit wiring to a 'User' source (not yet modeled).
+        // "Lethal Trifecta" requires Untrusted Input, which comes from:
+        // 1. Components declared in `[trust] untrusted_inputs` (user prompts, external feeds).
         // 2. OR 'Exfiltration' sources (HTTP) usually imply 'Untrusted' return values (search results).
         
         let has_untrusted = caps.contains(&Capability::UntrustedInput);
@@ -186,23 +845,76 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
 
         // Trifecta
         if has_untrusted && has_internal && has_exfiltration {
-             violations.push(SafetyViolation {
-                component: name.to_string(),
-                violation: ViolationType::LethalTrifecta,
-                details: format!("Component '{}' has access to Untrusted Input, Internal Data, and Exfiltration.", name),
-            });
+             violations.push(SafetyViolation::new(
+                name,
+                ViolationType::LethalTrifecta,
+                format!("Component '{}' has access to Untrusted Input, Internal Data, and Exfiltration.", name),
+            ));
         }
 
         // Deadly Duo
         if has_untrusted && has_destructive {
-            violations.push(SafetyViolation {
-                component: name.to_string(),
-                violation: ViolationType::DeadlyDuo,
-                details: format!("Component '{}' has access to Untrusted Input and Destructive Capabilities.", name),
-            });
+            violations.push(SafetyViolation::new(
+                name,
+                ViolationType::DeadlyDuo,
+                format!("Component '{}' has access to Untrusted Input and Destructive Capabilities.", name),
+            ));
+        }
+    }
+
+    let provenance = provenance::Provenance::new(wire_facts, &blueprint.trust.untrusted_inputs);
+
+    // 6. Follow data through the workflow, where outputs of one component become
+    // inputs of another without any wire between them.
+    if let Some(workflow) = &blueprint.workflow {
+        violations.extend(dataflow::check_workflow(workflow, &component_caps, catalogue, env, &blueprint.trust, &provenance));
+    }
+
+    // 7. Covert channels: a budgeted wire or edge must not be able to return more
+    // bits than allowed, whatever labels the data carries.
+    violations.extend(bandwidth::check_channels(&channels(blueprint, env)));
+
+    // 8. Each wire must link: whatever the consumer imports over it, the wire grants
+    // and the provider exports with the same types.
+    violations.extend(compat::check_wires(blueprint, env));
+
+    // 9. Settings must take effect where they are written.
+    violations.extend(settings::check_settings(blueprint, env));
+
+    // 10. Explain each violation: where its component's capabilities come from, and
+    // which wires to remove to break the combination it was flagged for. Dataflow
+    // violations are explained along the workflow when they are found.
+    for violation in &mut violations {
+        if violation.violation == ViolationType::DataflowTrifecta {
+            continue;
         }
+        let component = violation.component.as_str();
+        let Some(caps) = component_caps.get(component) else { continue };
+        let mut caps: Vec<Capability> = caps.iter().copied().collect();
+        caps.sort();
+        violation.sources = caps.iter().filter_map(|&capability| {
+            let wires = provenance.shortest_chain(component, capability)?;
+            Some(CapabilitySource { capability, wires, steps: Vec::new() })
+        }).collect();
+        violation.capabilities = caps;
+        violation.cut = match violation.violation {
+            ViolationType::LethalTrifecta => provenance.minimal_cut(
+                component,
+                &[Capability::UntrustedInput, Capability::InternalData, Capability::Exfiltration],
+            ),
+            ViolationType::DeadlyDuo => provenance.minimal_cut(
+                component,
+                &[Capability::UntrustedInput, Capability::Destructive],
+            ),
+            _ => Vec::new(),
+        }.into_iter().map(CutPoint::Wire).collect();
     }
 
+    // Report in the same order on every run, whatever order the checks found them in.
+    violations.sort_by(|a, b| {
+        (&a.component, &a.violation, &a.details).cmp(&(&b.component, &b.violation, &b.details))
+    });
+
     if violations.is_empty() {
         Ok(())
     } else {
@@ -210,36 +922,388 @@ pub fn verify(blueprint: &Blueprint) -> Result<(), Vec<SafetyViolation>> {
     }
 }
 
-fn infer_capabilities(interface: &str) -> Vec<Capability> {
-    let mut caps = Vec::new();
-    
-    // Heuristics based on interface names
-    // In a real system, this would be a lookup against a curated registry.
-    
-    // Exfiltration / Untrusted Source
-    if interface.contains("http") || interface.contains("search") || interface.contains("network") {
-        caps.push(Capability::Exfiltration);
-        caps.push(Capability::UntrustedInput); // Responses are untrusted
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verifies `blueprint` against `catalogue`, with every component pending so
+    /// that only the capability analysis speaks.
+    fn check_with(catalogue: &Catalogue, blueprint: &str) -> Vec<(String, ViolationType)> {
+        let blueprint: Blueprint = toml::from_str(blueprint).unwrap();
+        let env = Environment { pending: blueprint.components.keys().cloned().collect(), ..Default::default() };
+        match verify(&blueprint, catalogue, &env) {
+            Ok(()) => Vec::new(),
+            Err(violations) => violations.into_iter().map(|v| (v.component, v.violation)).collect(),
+        }
     }
-    
-    // Internal Knowledge
-    if (interface.contains("calendar") || interface.contains("filesystem") || interface.contains("read")) && !interface.contains("propose") {
-        caps.push(Capability::InternalData);
+
+    fn check(blueprint: &str) -> Vec<(String, ViolationType)> {
+        check_with(&Catalogue::builtin(), blueprint)
     }
-    
-    // Destructive
-    // IMPORTANT: 'propose' is NOT destructive because it requires human approval.
-    if (interface.contains("delete") || interface.contains("write") || interface.contains("modify")) && !interface.contains("propose") {
-        caps.push(Capability::Destructive);
+
+    fn flagged(violations: &[(String, ViolationType)], component: &str, violation: ViolationType) -> bool {
+        violations.iter().any(|(c, v)| c == component && *v == violation)
     }
-    
-    // Proposal (Safe)
-    if interface.contains("propose") {
-        caps.push(Capability::Proposal);
+
+    #[test]
+    fn empty_grant_still_passes_on_the_providers_authority() {
+        // completion is catalogued as `[]`, but the llm can still post whatever the
+        // agent hands it.
+        let violations = check(r#"
+            [components]
+            agent = "agent.wasm"
+            llm = "llm.wasm"
+            calendar = "calendar.wasm"
+
+            [wiring]
+            "agent.local:calendar-privacy/llm-api" = { provider = "llm.local:calendar-privacy/llm-api", functions = ["completion"] }
+            "llm.wasi:http/outgoing-handler" = "host.wasi:http/outgoing-handler"
+            "agent.local:calendar/read" = "calendar.local:calendar/read"
+
+            [trust]
+            untrusted_inputs = ["agent"]
+        "#);
+        assert!(flagged(&violations, "agent", ViolationType::LethalTrifecta), "{:?}", violations);
+    }
+
+    #[test]
+    fn empty_grant_stops_the_providers_data() {
+        // get-free-slots is catalogued as `[]`: the calendar's events stay with it.
+        let violations = check(r#"
+            [components]
+            agent = "agent.wasm"
+            calendar = "calendar.wasm"
+
+            [wiring]
+            "agent.local:calendar-privacy/calendar-api" = { provider = "calendar.local:calendar-privacy/calendar-api", functions = ["get-free-slots"] }
+            "agent.wasi:http/outgoing-handler" = "host.wasi:http/outgoing-handler"
+            "calendar.local:calendar/read" = "host.local:calendar/read"
+
+            [trust]
+            untrusted_inputs = ["agent"]
+        "#);
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    const POOL_AND_STORE: &str = r#"
+        [components]
+        agent = "agent.wasm"
+        pool = "pool.wasm"
+        store = "store.wasm"
+
+        [wiring]
+        "agent.local:pool/thread-pool" = "pool.local:pool/thread-pool"
+        "agent.local:store/remove-entry" = "store.local:store/remove-entry"
+
+        [trust]
+        untrusted_inputs = ["agent"]
+    "#;
+
+    #[test]
+    fn catalogue_classifies_interfaces_by_entry_not_name() {
+        // Names suggest a reader and nothing destructive; the catalogue says otherwise.
+        let catalogue = Catalogue::parse(r#"
+            version = 1
+
+            [interfaces."local:pool/thread-pool"]
+            capabilities = []
+
+            [interfaces."local:store/remove-entry"]
+            capabilities = ["destructive"]
+        "#).unwrap();
+        assert_eq!(check_with(&catalogue, POOL_AND_STORE), [("agent".to_string(), ViolationType::DeadlyDuo)]);
+    }
+
+    #[test]
+    fn uncatalogued_interfaces_are_unclassified_unless_heuristics_are_on() {
+        let strict = Catalogue::parse("version = 1").unwrap();
+        let violations = check_with(&strict, POOL_AND_STORE);
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|(c, v)| c == "agent" && *v == ViolationType::UnclassifiedInterface));
+
+        // By name, "thread-pool" reads calendars and "remove-entry" destroys nothing.
+        let heuristic = Catalogue::parse("version = 1\nheuristic_fallback = true").unwrap();
+        assert!(check_with(&heuristic, POOL_AND_STORE).is_empty());
+    }
+
+    #[test]
+    fn only_granted_functions_are_seeded() {
+        let blueprint = |function: &str| format!(r#"
+            [components]
+            agent = "agent.wasm"
+
+            [wiring]
+            "agent.local:calendar-privacy/calendar-api" = {{ provider = "host.local:calendar-privacy/calendar-api", functions = ["{}"] }}
+            "agent.wasi:http/outgoing-handler" = "host.wasi:http/outgoing-handler"
+        "#, function);
+        assert!(check(&blueprint("get-free-slots")).is_empty());
+        assert_eq!(check(&blueprint("get-events-sensitive")), [("agent".to_string(), ViolationType::LethalTrifecta)]);
+        assert_eq!(check(&blueprint("delete-everything")), [("agent".to_string(), ViolationType::UnclassifiedInterface)]);
+    }
+
+    #[test]
+    fn workflow_steps_calling_methods_are_classified_by_their_interface() {
+        let catalogue = Catalogue::parse(r#"
+            version = 1
+
+            [interfaces."local:store/rows".functions]
+            "[method]cursor.next" = ["internal-data"]
+
+            [interfaces."local:calendar-privacy/search-api".functions]
+            search = ["exfiltration", "untrusted-input"]
+        "#).unwrap();
+        let violations = check_with(&catalogue, r#"
+            [components]
+            store = "store.wasm"
+            searcher = "searcher.wasm"
+
+            [[workflow.steps]]
+            id = "next"
+            component = "store"
+            function = "local:store/rows.[method]cursor.next"
+
+            [[workflow.steps]]
+            id = "post"
+            component = "searcher"
+            function = "local:calendar-privacy/search-api.search"
+            input = "{{ next.output }}"
+        "#);
+        assert_eq!(violations, [("searcher".to_string(), ViolationType::DataflowTrifecta)]);
+    }
+
+    /// Calendar output passed through an LLM prompt into a search query.
+    fn search_workflow(calendar_function: &str, search_input: &str) -> String {
+        format!(r#"
+            [components]
+            calendar = "calendar.wasm"
+            llm = "llm.wasm"
+            searcher = "searcher.wasm"
+
+            [[workflow.steps]]
+            id = "read"
+            component = "calendar"
+            function = "local:calendar-privacy/calendar-api.{}"
+
+            [[workflow.steps]]
+            id = "prompt"
+            component = "llm"
+            function = "local:calendar-privacy/llm-api.completion"
+            input = "Summarise {{{{ read.output }}}}"
+
+            [[workflow.steps]]
+            id = "search"
+            component = "searcher"
+            function = "local:calendar-privacy/search-api.search"
+            input = "{}"
+        "#, calendar_function, search_input)
+    }
+
+    #[test]
+    fn workflow_taint_reaches_exfiltration_across_steps() {
+        // No component holds all three capabilities; the workflow joins them.
+        let violations = check(&search_workflow("get-events-sensitive", "{{ prompt.output }}"));
+        assert_eq!(violations, [("searcher".to_string(), ViolationType::DataflowTrifecta)]);
+    }
+
+    #[test]
+    fn workflow_taint_needs_internal_data_on_the_path() {
+        assert!(check(&search_workflow("get-free-slots", "{{ prompt.output }}")).is_empty());
+        // The search reads no earlier output, so the calendar data never reaches it.
+        assert!(check(&search_workflow("get-events-sensitive", "weekend plans")).is_empty());
+    }
+
+    #[test]
+    fn declared_untrusted_inputs_complete_the_trifecta() {
+        let blueprint = |trust: &str| format!(r#"
+            [components]
+            agent = "agent.wasm"
+
+            [wiring]
+            "agent.local:calendar/read" = "host.local:calendar/read"
+            "agent.local:email/send" = "host.local:email/send"
+
+            {}
+        "#, trust);
+        // Sending email brings no input back; only a prompt does.
+        assert!(check(&blueprint("")).is_empty());
+        assert_eq!(
+            check(&blueprint("[trust]\nuntrusted_inputs = [\"agent\"]")),
+            [("agent".to_string(), ViolationType::LethalTrifecta)]
+        );
+    }
+
+    #[test]
+    fn sanitisers_stop_data_but_not_authority() {
+        let mut catalogue = Catalogue::builtin();
+        catalogue.interfaces.insert("local:summary/api".to_string(), InterfaceEntry::default());
+        let blueprint = |trust: &str| format!(r#"
+            [components]
+            agent = "agent.wasm"
+            summariser = "summariser.wasm"
+
+            [wiring]
+            "agent.local:summary/api" = "summariser.local:summary/api"
+            "agent.wasi:http/outgoing-handler" = "host.wasi:http/outgoing-handler"
+            "summariser.local:calendar/read" = "host.local:calendar/read"
+            "summariser.local:calendar/delete" = "host.local:calendar/delete"
+
+            [trust]
+            untrusted_inputs = ["agent"]
+            {}
+        "#, trust);
+        let agent = |violation| ("agent".to_string(), violation);
+        assert_eq!(
+            check_with(&catalogue, &blueprint("")),
+            [agent(ViolationType::LethalTrifecta), agent(ViolationType::DeadlyDuo)]
+        );
+        // The calendar stays behind the sanitiser; its delete grant does not.
+        let sanitised = "[[trust.sanitisers]]\ncomponent = \"summariser\"";
+        assert_eq!(check_with(&catalogue, &blueprint(sanitised)), [agent(ViolationType::DeadlyDuo)]);
+        // A sanitiser declared for another function does not cover this wire.
+        let elsewhere = "[[trust.sanitisers]]\ncomponent = \"summariser\"\nfunctions = [\"local:summary/api.brief\"]";
+        assert_eq!(check_with(&catalogue, &blueprint(elsewhere)).len(), 2);
+    }
+
+    #[test]
+    fn sanitiser_steps_clear_workflow_taint() {
+        let workflow = search_workflow("get-events-sensitive", "{{ prompt.output }}");
+        let sanitised = format!("{}\n[[trust.sanitisers]]\ncomponent = \"llm\"\nfunctions = [\"local:calendar-privacy/llm-api.completion\"]", workflow);
+        assert!(check(&sanitised).is_empty());
+    }
+
+    const SIZES_WIT: &str = "
+        package local:sizes;
+
+        interface llm-api {
+            enum user-state { busy, free, away, sleeping, working }
+            predict-state: func(prompt: string) -> user-state;
+            is-busy: func() -> bool;
+            completion: func(prompt: string) -> string;
+        }
+    ";
+
+    /// Verifies `blueprint` with `llm` sized from `SIZES_WIT` and every other
+    /// component pending.
+    fn check_sized(blueprint: &str) -> Vec<(String, ViolationType)> {
+        let path = std::env::temp_dir().join(format!("pypes-sizes-{}.wit", std::process::id()));
+        std::fs::write(&path, SIZES_WIT).unwrap();
+        let blueprint: Blueprint = toml::from_str(blueprint).unwrap();
+        let mut env = Environment {
+            pending: blueprint.components.keys().filter(|c| *c != "llm").cloned().collect(),
+            ..Default::default()
+        };
+        env.wit.insert("llm".to_string(), WitLoader::load(&path).unwrap());
+        let mut catalogue = Catalogue::builtin();
+        catalogue.interfaces.insert("local:sizes/llm-api".to_string(), InterfaceEntry::default());
+        match verify(&blueprint, &catalogue, &env) {
+            Ok(()) => Vec::new(),
+            Err(violations) => violations.into_iter().map(|v| (v.component, v.violation)).collect(),
+        }
+    }
+
+    #[test]
+    fn wire_budgets_hold_the_widest_granted_result() {
+        let blueprint = |functions: &str, max_bits: u32| format!(r#"
+            [components]
+            agent = "agent.wasm"
+            llm = "llm.wasm"
+
+            [wiring]
+            "agent.local:sizes/llm-api" = {{ provider = "llm.local:sizes/llm-api", functions = {}, max_bits = {} }}
+        "#, functions, max_bits);
+        let exceeded = [("agent".to_string(), ViolationType::BandwidthExceeded)];
+        // Five states take log2(5) = 2.3 bits.
+        assert!(check_sized(&blueprint(r#"["predict-state", "is-busy"]"#, 3)).is_empty());
+        assert_eq!(check_sized(&blueprint(r#"["predict-state"]"#, 2)), exceeded);
+        assert_eq!(check_sized(&blueprint(r#"["predict-state", "completion"]"#, 64)), exceeded);
+    }
+
+    #[test]
+    fn workflow_edge_budgets_hold_the_producers_result() {
+        let blueprint = |function: &str| format!(r#"
+            [components]
+            llm = "llm.wasm"
+            searcher = "searcher.wasm"
+
+            [[workflow.steps]]
+            id = "analyse"
+            component = "llm"
+            function = "local:sizes/llm-api.{}"
+
+            [[workflow.steps]]
+            id = "search"
+            component = "searcher"
+            function = "local:calendar-privacy/search-api.search"
+            input = "{{{{ analyse.output }}}}"
+            max_bits = 3
+        "#, function);
+        assert!(check_sized(&blueprint("predict-state")).is_empty());
+        assert_eq!(
+            check_sized(&blueprint("completion")),
+            [("searcher".to_string(), ViolationType::BandwidthExceeded)]
+        );
+    }
+
+    #[test]
+    fn budgets_fail_without_wit_to_size_them() {
+        let blueprint: Blueprint = toml::from_str(r#"
+            [components]
+            agent = "agent.wasm"
+            calendar = "calendar.wasm"
+
+            [wiring]
+            "agent.local:calendar/read" = { provider = "calendar.local:calendar/read", max_bits = 8 }
+        "#).unwrap();
+        // The calendar is not pending, but has no WIT.
+        let env = Environment { pending: ["agent".to_string()].into(), ..Default::default() };
+        let violations = verify(&blueprint, &Catalogue::builtin(), &env).unwrap_err();
+        assert!(violations.iter().any(|v| v.violation == ViolationType::BandwidthExceeded
+            && v.details.contains("no WIT describes what it returns")));
+        // Pending, it is sized by the run that fetches it.
+        let env = Environment { pending: ["agent".to_string(), "calendar".to_string()].into(), ..Default::default() };
+        assert!(verify(&blueprint, &Catalogue::builtin(), &env).is_ok());
+    }
+
+    #[test]
+    fn diode_wires_carry_no_capabilities_of_functions_passing_handles() {
+        use component_types::{FuncType, ValueType};
+
+        let mut catalogue = Catalogue::builtin();
+        catalogue.interfaces.insert("local:db/rows".to_string(), InterfaceEntry {
+            capabilities: Vec::new(),
+            functions: [("open".to_string(), vec![Capability::InternalData]), ("count".to_string(), Vec::new())].into(),
+        });
+        // `open` hands out a cursor over the rows; `count` only a number.
+        let functions = [
+            ("open".to_string(), FuncType { params: Vec::new(), results: vec![ValueType::Own("cursor".to_string())] }),
+            ("count".to_string(), FuncType { params: Vec::new(), results: vec![ValueType::U32] }),
+        ];
+        let store = ComponentTypes {
+            imports: Default::default(),
+            exports: [("local:db/rows".to_string(), Item::Instance { functions: functions.into(), resources: vec!["cursor".to_string()] })].into(),
+        };
+        let verify_with = |diode: bool| {
+            let blueprint: Blueprint = toml::from_str(&format!(r#"
+                [components]
+                agent = "agent.wasm"
+                store = "store.wasm"
+
+                [wiring]
+                "agent.local:db/rows" = {{ provider = "store.local:db/rows", diode = {} }}
+                "agent.wasi:http/outgoing-handler" = "host.wasi:http/outgoing-handler"
+
+                [trust]
+                untrusted_inputs = ["agent"]
+            "#, diode)).unwrap();
+            let env = Environment {
+                types: [("store".to_string(), store.clone())].into(),
+                pending: ["agent".to_string()].into(),
+                ..Default::default()
+            };
+            verify(&blueprint, &catalogue, &env).map_err(|v| v.into_iter().map(|v| v.violation).collect::<Vec<_>>())
+        };
+        assert_eq!(verify_with(false), Err(vec![ViolationType::LethalTrifecta]));
+        // The diode refuses `open`, so only `count` can be called.
+        assert!(verify_with(true).is_ok());
     }
-    
-    // Special case: LLM inference (usually compute, but if wired to others...)
-    // Treat LLM as benign by default, it just processes data.
-    
-    caps
 }
@@ -0,0 +1,274 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{Capability, Flow, Wire};
+
+/// What one wire contributes to the capability graph.
+pub(crate) struct WireFacts<'a> {
+    pub wire: Wire,
+    pub consumer: &'a str,
+    pub provider: &'a str,
+    pub flow: Flow,
+    /// Capabilities the wire hands its consumer directly (catalogue, preopen mode).
+    pub grants: HashSet<Capability>,
+}
+
+impl WireFacts<'_> {
+    /// Whether the provider's own `capability` reaches the consumer over this wire.
+    fn carries(&self, capability: Capability) -> bool {
+        self.flow.carries(capability)
+    }
+}
+
+/// Explains violations after the fact: which wires gave a component a capability,
+/// and which wires to remove to take it away again.
+pub(crate) struct Provenance<'a> {
+    /// Sorted by consumer key, so explanations do not depend on hash order.
+    wires: Vec<WireFacts<'a>>,
+    untrusted_inputs: &'a [String],
+}
+
+impl<'a> Provenance<'a> {
+    pub fn new(mut wires: Vec<WireFacts<'a>>, untrusted_inputs: &'a [String]) -> Self {
+        wires.sort_by(|a, b| a.wire.consumer.cmp(&b.wire.consumer));
+        Self { wires, untrusted_inputs }
+    }
+
+    fn declared(&self, component: &str, capability: Capability) -> bool {
+        capability == Capability::UntrustedInput && self.untrusted_inputs.iter().any(|c| c == component)
+    }
+
+    /// The fewest wires, starting at `component`, through which it obtains
+    /// `capability`. Empty when the component is itself declared untrusted.
+    pub fn shortest_chain(&self, component: &str, capability: Capability) -> Option<Vec<Wire>> {
+        if self.declared(component, capability) {
+            return Some(Vec::new());
+        }
+
+        // Breadth-first over components; every queued path is one wire longer than
+        // the last, so the first source found is the nearest.
+        let mut visited = HashSet::from([component]);
+        let mut queue = VecDeque::from([(component, Vec::new())]);
+        while let Some((node, path)) = queue.pop_front() {
+            for facts in self.wires.iter().filter(|w| w.consumer == node) {
+                let mut next = path.clone();
+                next.push(facts.wire.clone());
+                if facts.grants.contains(&capability) {
+                    return Some(next);
+                }
+                if facts.carries(capability) && visited.insert(facts.provider) {
+                    if self.declared(facts.provider, capability) {
+                        return Some(next);
+                    }
+                    queue.push_back((facts.provider, next));
+                }
+            }
+        }
+        None
+    }
+
+    /// The smallest set of wires whose removal takes one of `capabilities` away from
+    /// `component`, which is enough to break a violation needing all of them.
+    /// Empty when none can be cut, e.g. every one is declared on the component itself.
+    pub fn minimal_cut(&self, component: &str, capabilities: &[Capability]) -> Vec<Wire> {
+        capabilities.iter()
+            .filter_map(|&capability| self.cut_for(component, capability))
+            .min_by_key(|cut| cut.len())
+            .unwrap_or_default()
+    }
+
+    /// Minimum cut between `component` and every source of `capability`, as unit
+    /// capacity max-flow. Each wire is a node entered through a capacity-1 arc
+    /// (cutting that arc removes the wire) and left through unbounded arcs to the
+    /// sink (if it grants the capability) and to its provider (if it carries it).
+    pub fn cut_for(&self, component: &str, capability: Capability) -> Option<Vec<Wire>> {
+        let mut components: Vec<&str> = vec![component];
+        for facts in &self.wires {
+            for name in [facts.consumer, facts.provider] {
+                if !components.contains(&name) {
+                    components.push(name);
+                }
+            }
+        }
+        let wire_node = |i: usize| components.len() + i;
+        let sink = components.len() + self.wires.len();
+        let unbounded = self.wires.len() as u32 + 1;
+
+        let mut capacity = vec![vec![0u32; sink + 1]; sink + 1];
+        for (c, name) in components.iter().enumerate() {
+            if self.declared(name, capability) {
+                capacity[c][sink] = unbounded;
+            }
+        }
+        for (i, facts) in self.wires.iter().enumerate() {
+            let consumer = components.iter().position(|&c| c == facts.consumer)?;
+            let provider = components.iter().position(|&c| c == facts.provider)?;
+            capacity[consumer][wire_node(i)] = 1;
+            if facts.grants.contains(&capability) {
+                capacity[wire_node(i)][sink] = unbounded;
+            }
+            if facts.carries(capability) {
+                capacity[wire_node(i)][provider] = unbounded;
+            }
+        }
+
+        // Wires entered from the source side of the residual graph but not reached
+        // from it are the cut.
+        let reachable = source_side(&mut capacity, 0, sink, unbounded)?;
+        let cut: Vec<Wire> = self.wires.iter().enumerate()
+            .filter(|&(i, facts)| {
+                let consumer = components.iter().position(|&c| c == facts.consumer);
+                consumer.is_some_and(|c| reachable[c]) && !reachable[wire_node(i)]
+            })
+            .map(|(_, facts)| facts.wire.clone())
+            .collect();
+        (!cut.is_empty()).then_some(cut)
+    }
+}
+
+/// Pushes unit flow from `source` to `sink` along shortest residual paths
+/// (Edmonds-Karp) until none is left, and returns which nodes the source still
+/// reaches: the source side of a minimum cut. `None` once the flow reaches `limit`,
+/// the capacity of arcs that may not be cut.
+pub(crate) fn source_side(capacity: &mut [Vec<u32>], source: usize, sink: usize, limit: u32) -> Option<Vec<bool>> {
+    let mut flow = 0;
+    loop {
+        let parents = residual_bfs(capacity, source);
+        if parents[sink].is_none() {
+            return Some(parents.iter().map(Option::is_some).collect());
+        }
+        let mut node = sink;
+        while let Some(parent) = parents[node].filter(|_| node != source) {
+            capacity[parent][node] -= 1;
+            capacity[node][parent] += 1;
+            node = parent;
+        }
+        flow += 1;
+        if flow >= limit {
+            return None;
+        }
+    }
+}
+
+/// Parent of each node on a shortest residual path from `source` (the source is its
+/// own parent), or `None` if unreachable.
+fn residual_bfs(capacity: &[Vec<u32>], source: usize) -> Vec<Option<usize>> {
+    let mut parents = vec![None; capacity.len()];
+    parents[source] = Some(source);
+    let mut queue = VecDeque::from([source]);
+    while let Some(node) = queue.pop_front() {
+        for (next, &cap) in capacity[node].iter().enumerate() {
+            if cap > 0 && parents[next].is_none() {
+                parents[next] = Some(node);
+                queue.push_back(next);
+            }
+        }
+    }
+    parents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wire<'a>(consumer: &'a str, provider: &'a str, grants: &[Capability]) -> WireFacts<'a> {
+        WireFacts {
+            wire: Wire { consumer: format!("{}.in", consumer), provider: format!("{}.out", provider) },
+            consumer,
+            provider,
+            flow: Flow::Everything,
+            grants: grants.iter().copied().collect(),
+        }
+    }
+
+    fn consumers(wires: &[Wire]) -> Vec<&str> {
+        wires.iter().map(|w| w.consumer.as_str()).collect()
+    }
+
+    #[test]
+    fn cut_takes_the_shared_wire_over_parallel_ones() {
+        // agent reaches the store through two readers; only the store's own wire
+        // grants the data, so cutting it beats cutting both readers.
+        let wires = vec![
+            wire("agent", "reader_a", &[]),
+            wire("agent", "reader_b", &[]),
+            wire("reader_a", "store", &[]),
+            wire("reader_b", "store", &[]),
+            wire("store", "host", &[Capability::InternalData]),
+        ];
+        let provenance = Provenance::new(wires, &[]);
+        let cut = provenance.cut_for("agent", Capability::InternalData).unwrap();
+        assert_eq!(consumers(&cut), ["store.in"]);
+    }
+
+    #[test]
+    fn cut_takes_the_parallel_wires_when_they_are_fewer() {
+        let wires = vec![
+            wire("agent", "reader_a", &[Capability::InternalData]),
+            wire("agent", "reader_b", &[Capability::InternalData]),
+        ];
+        let provenance = Provenance::new(wires, &[]);
+        let cut = provenance.cut_for("agent", Capability::InternalData).unwrap();
+        assert_eq!(consumers(&cut), ["agent.in", "agent.in"]);
+    }
+
+    #[test]
+    fn nothing_cuts_a_declared_capability() {
+        let untrusted = vec!["agent".to_string()];
+        let wires = vec![wire("agent", "search", &[Capability::UntrustedInput])];
+        let provenance = Provenance::new(wires, &untrusted);
+        assert_eq!(provenance.cut_for("agent", Capability::UntrustedInput), None);
+        assert_eq!(provenance.shortest_chain("agent", Capability::UntrustedInput), Some(Vec::new()));
+    }
+
+    #[test]
+    fn cut_stops_where_the_flow_does() {
+        let mut sanitised = wire("agent", "llm", &[]);
+        sanitised.flow = Flow::Authority;
+        let wires = vec![sanitised, wire("llm", "host", &[Capability::InternalData, Capability::Exfiltration])];
+        let provenance = Provenance::new(wires, &[]);
+        assert_eq!(provenance.cut_for("agent", Capability::InternalData), None);
+        assert_eq!(consumers(&provenance.cut_for("agent", Capability::Exfiltration).unwrap()), ["agent.in"]);
+    }
+
+    #[test]
+    fn minimal_cut_picks_the_cheapest_capability() {
+        let wires = vec![
+            wire("agent", "a", &[Capability::InternalData]),
+            wire("agent", "b", &[Capability::InternalData]),
+            wire("agent", "web", &[Capability::Exfiltration]),
+        ];
+        let provenance = Provenance::new(wires, &[]);
+        let cut = provenance.minimal_cut("agent", &[Capability::InternalData, Capability::Exfiltration]);
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].provider, "web.out");
+    }
+
+    #[test]
+    fn shortest_chain_follows_the_fewest_wires() {
+        let wires = vec![
+            wire("agent", "a", &[]),
+            wire("a", "b", &[]),
+            wire("b", "host", &[Capability::Exfiltration]),
+            wire("agent", "c", &[]),
+            wire("c", "host", &[Capability::Exfiltration]),
+        ];
+        let provenance = Provenance::new(wires, &[]);
+        let chain = provenance.shortest_chain("agent", Capability::Exfiltration).unwrap();
+        assert_eq!(consumers(&chain), ["agent.in", "c.in"]);
+    }
+
+    #[test]
+    fn source_side_separates_a_minimum_cut() {
+        // 0 -> 1 -> 3 and 0 -> 2 -> 3, with the 1 -> 3 and 0 -> 2 arcs narrow.
+        let mut capacity = vec![vec![0u32; 4]; 4];
+        capacity[0][1] = 9;
+        capacity[1][3] = 1;
+        capacity[0][2] = 1;
+        capacity[2][3] = 9;
+        let reachable = source_side(&mut capacity, 0, 3, 9).unwrap();
+        assert_eq!(reachable, [true, true, false, false]);
+        // A flow as wide as the limit means only uncuttable arcs separate them.
+        let mut capacity = vec![vec![0, 5], vec![0, 0]];
+        assert_eq!(source_side(&mut capacity, 0, 1, 5), None);
+    }
+}
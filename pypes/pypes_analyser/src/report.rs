@@ -0,0 +1,81 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{SafetyViolation, ViolationType};
+
+/// Format of `Report::to_json`. Bumped whenever a field changes meaning or goes away.
+pub const REPORT_VERSION: u32 = 2;
+
+/// Outcome of verifying one blueprint, for CI gates and review bots.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub version: u32,
+    /// Path of the blueprint, as given on the command line.
+    pub blueprint: &'a str,
+    pub passed: bool,
+    pub violations: &'a [SafetyViolation],
+}
+
+impl<'a> Report<'a> {
+    pub fn new(blueprint: &'a str, violations: &'a [SafetyViolation]) -> Self {
+        Self { version: REPORT_VERSION, blueprint, passed: violations.is_empty(), violations }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+
+    /// SARIF 2.1.0, one result per violation, located at the blueprint file.
+    pub fn to_sarif(&self) -> String {
+        let results: Vec<Value> = self.violations.iter().map(|v| {
+            json!({
+                "ruleId": rule_id(&v.violation),
+                "level": "error",
+                "message": { "text": v.details },
+                "locations": [{
+                    "physicalLocation": { "artifactLocation": { "uri": self.blueprint } },
+                    "logicalLocations": [{ "name": v.component, "kind": "module" }],
+                }],
+                "properties": {
+                    "capabilities": v.capabilities,
+                    "sources": v.sources,
+                    "cut": v.cut,
+                },
+            })
+        }).collect();
+
+        let rules: Vec<Value> = RULES.iter().map(|(violation, description)| {
+            json!({ "id": rule_id(violation), "shortDescription": { "text": description } })
+        }).collect();
+
+        let sarif = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "pypes",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        });
+        serde_json::to_string_pretty(&sarif).expect("reports always serialize")
+    }
+}
+
+const RULES: [(ViolationType, &str); 7] = [
+    (ViolationType::LethalTrifecta, "Untrusted Input, Internal Data and Exfiltration meet in one component"),
+    (ViolationType::DeadlyDuo, "Untrusted Input meets a Destructive capability"),
+    (ViolationType::UnclassifiedInterface, "Wired interface is missing from the capability catalogue"),
+    (ViolationType::DataflowTrifecta, "Workflow sends Internal Data to an Exfiltration step with Untrusted Input on the path"),
+    (ViolationType::BandwidthExceeded, "Wire or workflow edge can carry more bits than its budget"),
+    (ViolationType::TypeMismatch, "Consumer's import does not match the provider's export"),
+    (ViolationType::MisconfiguredWire, "Wire carries settings that cannot take effect on it"),
+];
+
+fn rule_id(violation: &ViolationType) -> String {
+    format!("{:?}", violation)
+}
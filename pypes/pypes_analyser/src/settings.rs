@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+use crate::{Blueprint, Environment, SafetyViolation, ViolationType, BUILTIN_MIDDLEWARE};
+
+/// Reports wire settings that could not take effect where they are, which would
+/// otherwise leave a blueprint looking more locked down than it runs: a host
+/// grant on a wire to another interface, grants that overlap, middleware on host
+/// wires, which are linked directly and never run it, middleware no runtime
+/// knows, and settings for middleware the wire does not have (a misspelt key
+/// included).
+pub(crate) fn check_settings(blueprint: &Blueprint, env: &Environment) -> Vec<SafetyViolation> {
+    let mut violations = Vec::new();
+    // Consumer -> wires granting it outbound HTTP or environment variables, and
+    // the guest paths it is given.
+    let mut http_wires: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut env_wires: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut guest_paths: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+
+    let mut wires: Vec<_> = blueprint.wiring.iter().collect();
+    wires.sort_by_key(|(consumer_key, _)| consumer_key.as_str());
+    for (consumer_key, connection) in wires {
+        let consumer = consumer_key.split('.').next().unwrap_or(consumer_key);
+        let provider_key = connection.provider();
+        let wire = format!("wire '{}' -> '{}'", consumer_key, provider_key);
+        // "host.wasi:http/outgoing-handler@0.2.0" -> "wasi:http/outgoing-handler"
+        let host_interface = provider_key.strip_prefix("host.").map(|i| i.split('@').next().unwrap_or(i));
+
+        let known = |name: &str| {
+            BUILTIN_MIDDLEWARE.contains(&name) || blueprint.guards.contains_key(name) || env.middleware.contains(name)
+        };
+        for name in connection.middleware().iter().filter(|name| !known(name)) {
+            violations.push(SafetyViolation::new(
+                consumer,
+                ViolationType::MisconfiguredWire,
+                format!("{}: unknown middleware '{}'; it is not built in, registered or a guard.", wire, name),
+            ));
+        }
+        let on_wire = |name: &str| connection.middleware().iter().any(|m| m == name);
+        // (key, the middleware reading it) for every setting on the wire.
+        let typed = [
+            ("dlp", "dlp", connection.dlp().is_some()),
+            ("budget", "budget", connection.budget().is_some()),
+            ("validate", "validate", connection.validate().is_some()),
+            ("rate_limit", "rate_limit", connection.rate_limit().is_some()),
+            ("timeout_ms", "timeout", connection.timeout_ms().is_some()),
+        ];
+        let typed = typed.into_iter().filter(|&(.., set)| set).map(|(key, middleware, _)| (key, middleware));
+        let named = connection.setting_names().into_iter().map(|key| (key, key));
+        for (key, middleware) in typed.chain(named) {
+            if !on_wire(middleware) {
+                let details = if known(middleware) {
+                    format!("{}: `{}` is set, but '{}' is not in the wire's middleware.", wire, key, middleware)
+                } else {
+                    format!("{}: unknown setting `{}`.", wire, key)
+                };
+                violations.push(SafetyViolation::new(consumer, ViolationType::MisconfiguredWire, details));
+            }
+        }
+        if host_interface.is_some() && !connection.middleware().is_empty() {
+            violations.push(SafetyViolation::new(
+                consumer,
+                ViolationType::MisconfiguredWire,
+                format!(
+                    "{}: middleware ({}) only runs on wires between components; host wires are linked directly.",
+                    wire, connection.middleware().join(", ")
+                ),
+            ));
+        }
+        if let Some(preopen) = connection.preopen() {
+            if host_interface.is_some_and(|i| i.starts_with("wasi:filesystem/")) {
+                guest_paths.entry((consumer, &preopen.guest_path)).or_default().push(consumer_key);
+            } else {
+                violations.push(SafetyViolation::new(
+                    consumer,
+                    ViolationType::MisconfiguredWire,
+                    format!("{}: `preopen` only applies to host.wasi:filesystem/* wires.", wire),
+                ));
+            }
+        }
+        if connection.http().is_some() {
+            if host_interface == Some("wasi:http/outgoing-handler") {
+                http_wires.entry(consumer).or_default().push(consumer_key);
+            } else {
+                violations.push(SafetyViolation::new(
+                    consumer,
+                    ViolationType::MisconfiguredWire,
+                    format!("{}: `http` only applies to host.wasi:http/outgoing-handler wires.", wire),
+                ));
+            }
+        }
+        if connection.env().is_some() {
+            if host_interface == Some("wasi:cli/environment") {
+                env_wires.entry(consumer).or_default().push(consumer_key);
+            } else {
+                violations.push(SafetyViolation::new(
+                    consumer,
+                    ViolationType::MisconfiguredWire,
+                    format!("{}: `env` only applies to host.wasi:cli/environment wires.", wire),
+                ));
+            }
+        }
+    }
+
+    for (consumer, keys) in http_wires.into_iter().filter(|(_, keys)| keys.len() > 1) {
+        violations.push(SafetyViolation::new(
+            consumer,
+            ViolationType::MisconfiguredWire,
+            format!("'{}' has `http` grants on several wires ({}); list every allowed host on one.", consumer, keys.join(", ")),
+        ));
+    }
+    for (consumer, keys) in env_wires.into_iter().filter(|(_, keys)| keys.len() > 1) {
+        violations.push(SafetyViolation::new(
+            consumer,
+            ViolationType::MisconfiguredWire,
+            format!("'{}' has `env` grants on several wires ({}); list every variable on one.", consumer, keys.join(", ")),
+        ));
+    }
+    for ((consumer, guest_path), keys) in guest_paths.into_iter().filter(|(_, keys)| keys.len() > 1) {
+        violations.push(SafetyViolation::new(
+            consumer,
+            ViolationType::MisconfiguredWire,
+            format!("'{}' is given several directories at '{}' ({}).", consumer, guest_path, keys.join(", ")),
+        ));
+    }
+
+    violations
+}
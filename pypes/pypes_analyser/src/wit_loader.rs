@@ -0,0 +1,108 @@
+use anyhow::{Result, Context, anyhow};
+use std::path::Path;
+use std::fs;
+use wit_parser::{Function, InterfaceId, Resolve, TypeDefKind, UnresolvedPackage, WorldItem, WorldKey};
+use crate::catalogue::split_function;
+
+pub struct WitLoader {
+    pub resolve: Resolve,
+    pub main_package: wit_parser::PackageId,
+}
+
+impl WitLoader {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut resolve = Resolve::new();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read WIT file: {:?}", path))?;
+            
+        let pkg = UnresolvedPackage::parse(path, &content)?;
+        let main_package = resolve.push(pkg)?;
+        
+        Ok(Self {
+            resolve,
+            main_package,
+        })
+    }
+    
+    /// "namespace:name" of the main package, without its version.
+    pub fn package(&self) -> String {
+        let name = &self.resolve.packages[self.main_package].name;
+        format!("{}:{}", name.namespace, name.name)
+    }
+
+    pub fn get_interface_exports(&self, interface_name: &str) -> Result<Vec<String>> {
+        let interface_id = self.interface_id(interface_name)?;
+        let iface = &self.resolve.interfaces[interface_id];
+        Ok(iface.functions.keys().cloned().collect())
+    }
+
+    /// Names of the resource types an interface defines.
+    pub fn get_interface_resources(&self, interface_name: &str) -> Result<Vec<String>> {
+        let iface = &self.resolve.interfaces[self.interface_id(interface_name)?];
+        Ok(iface.types.iter()
+            .filter(|(_, id)| matches!(self.resolve.types[**id].kind, TypeDefKind::Resource))
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+
+    /// Looks up "interface.function" (methods included, as in
+    /// "interface.[method]cursor.next"), or a bare root function exported by one
+    /// of the package's worlds.
+    pub fn get_function(&self, qualified_name: &str) -> Option<&Function> {
+        match split_function(qualified_name) {
+            Some((interface_name, func_name)) => {
+                let interface_id = self.interface_id(interface_name).ok()?;
+                self.resolve.interfaces[interface_id].functions.get(func_name)
+            }
+            None => self.root_function(qualified_name, false),
+        }
+    }
+
+    /// A bare root function imported by one of the package's worlds.
+    pub fn get_root_import(&self, name: &str) -> Option<&Function> {
+        self.root_function(name, true)
+    }
+
+    fn root_function(&self, name: &str, imported: bool) -> Option<&Function> {
+        let pkg = &self.resolve.packages[self.main_package];
+        pkg.worlds.values().find_map(|&world_id| {
+            let world = &self.resolve.worlds[world_id];
+            let items = if imported { &world.imports } else { &world.exports };
+            items.iter().find_map(|(key, item)| match (key, item) {
+                (WorldKey::Name(n), WorldItem::Function(func)) if n == name => Some(func),
+                _ => None,
+            })
+        })
+    }
+
+    /// Resolves "ns:pkg/interface[@version]" in whichever package of the WIT
+    /// carries that name, as a world imports or exports it; a bare interface name
+    /// only in the main package.
+    fn interface_id(&self, interface_name: &str) -> Result<InterfaceId> {
+        let (package, interface) = match interface_name.split_once('/') {
+            Some((package, interface)) => (Some(package), interface),
+            None => (None, interface_name),
+        };
+        let (interface, version) = match interface.split_once('@') {
+            Some((interface, version)) => (interface, Some(version)),
+            None => (interface, None),
+        };
+        let package_id = match package {
+            None => Some(self.main_package),
+            Some(package) => self.resolve.packages.iter()
+                .find(|(_, pkg)| {
+                    pkg.name.to_string().split('@').next() == Some(package)
+                        && version.is_none_or(|v| pkg.name.version.as_ref().is_some_and(|pv| pv.to_string() == v))
+                })
+                .map(|(id, _)| id),
+        };
+        package_id
+            .and_then(|id| self.resolve.packages[id].interfaces.get(interface).copied())
+            .ok_or_else(|| {
+                let known: Vec<String> = self.resolve.packages.iter()
+                    .flat_map(|(_, pkg)| pkg.interfaces.keys().map(move |name| format!("{}/{}", pkg.name, name)))
+                    .collect();
+                anyhow!("Interface '{}' not found in WIT. Available: {:?}", interface_name, known)
+            })
+    }
+}